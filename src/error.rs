@@ -0,0 +1,60 @@
+use crate::linger::PoisonReason;
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error as IoError;
+use std::result::Result as StdResult;
+
+pub type Result<T> = StdResult<T, Error>;
+
+/// Reasons a timed function could not be launched or resumed.  Other than `Os` and `Poisoned`,
+/// these indicate that the process is out of some resource, so the caller may retry once it has
+/// freed some.
+#[derive(Debug)]
+pub enum Error {
+	/// Every libset is already in use by another timed function.
+	NoLibset,
+	/// The calling kernel thread's preemption signal or timer could not be set up.
+	NoSignal(IoError),
+	/// An execution stack could not be allocated.
+	StackAlloc,
+	/// A thread-control block could not be allocated or installed.  Only installation failures
+	/// come with an underlying system error.
+	Tls(Option<IoError>),
+	/// Some other system call failed.
+	Os(IoError),
+	/// The timed function was abandoned without producing a value, so it can't be resumed.
+	Poisoned(PoisonReason),
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		match self {
+		Error::NoLibset => write!(f, "too many active timed functions"),
+		Error::NoSignal(or) => write!(f, "unable to set up preemption signal: {}", or),
+		Error::StackAlloc => write!(f, "unable to allocate stack"),
+		Error::Tls(None) => write!(f, "unable to allocate thread-control block"),
+		Error::Tls(Some(or)) => write!(f, "unable to install thread-control block: {}", or),
+		Error::Os(or) => write!(f, "{}", or),
+		Error::Poisoned(PoisonReason::Panic) => write!(f, "timed function panicked"),
+		Error::Poisoned(PoisonReason::StackOverflow) => write!(f, "timed function overflowed its stack"),
+		Error::Poisoned(PoisonReason::Canceled) => write!(f, "timed function was canceled"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+		Error::NoSignal(or) | Error::Tls(Some(or)) | Error::Os(or) => Some(or),
+		_ => None,
+		}
+	}
+}
+
+impl From<IoError> for Error {
+	fn from(or: IoError) -> Self {
+		Error::Os(or)
+	}
+}
@@ -6,7 +6,9 @@ use std::thread::Result;
 
 #[repr(C)]
 pub struct Linger {
+	// Also set if the timed function was abandoned, in which case is_poisoned is too.
 	is_complete: bool,
+	is_poisoned: bool,
 	continuation: Lingerer<(), dyn FnMut(*mut Option<Result<()>>) + Send>,
 }
 
@@ -24,6 +26,7 @@ extern fn launch(fun: unsafe extern fn(*mut c_void), us: u64, args: *mut c_void)
 	if let Ok(timed) = timed {
 		Linger {
 			is_complete: timed.is_completion(),
+			is_poisoned: false,
 			continuation: timed.erase(),
 		}
 	} else {
@@ -39,7 +42,8 @@ extern fn resume(timed: Option<&mut Linger>, us: u64) {
 		if resume(&mut timed.continuation, us).is_err() {
 			abort();
 		}
-		timed.is_complete = timed.continuation.is_completion();
+		timed.is_poisoned = timed.continuation.poisoned().is_some();
+		timed.is_complete = timed.continuation.is_completion() || timed.is_poisoned;
 	} else {
 		abort();
 	}
@@ -1,7 +1,7 @@
+use crate::error::Result;
 use crate::linger::Linger;
 
 use std::future::Future;
-use std::io::Result;
 use std::pin::Pin;
 use std::sync::mpsc::SyncSender;
 use std::task::Context;
@@ -69,6 +69,7 @@ Future for PreemptiveFuture<T, F, P, I> {
 	type Output = Result<T>;
 
 	fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+		use crate::error::Error;
 		use crate::linger::resume;
 
 		if let Some(mut fun) = self.fun.take() {
@@ -78,6 +79,9 @@ Future for PreemptiveFuture<T, F, P, I> {
 			} else {
 				if let Linger::Completion(ready) = fun {
 					Poll::Ready(Ok(ready))
+				} else if let Some(reason) = fun.poisoned() {
+					// It will never run again, so don't keep polling it.
+					Poll::Ready(Err(Error::Poisoned(reason)))
 				} else {
 					let timeout = ! fun.yielded();
 					self.fun.replace(fun);
@@ -1,23 +1,230 @@
+use crate::reusable::ReusableSync;
+use crate::reusable::SyncPool;
 use crate::reusable::SyncResult;
+use crate::reusable::Ticket;
+use crate::slots::Slot;
 
 use gotcha::Group as GotchaGroup;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
 
-pub fn assign_group() -> SyncResult<'static, GotchaGroup> {
+// Number of groups that have ever been handed out by libgotcha, each of which lives in a slot.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+// Number of slots handed off for renewal that haven't yet made it back to the pool.
+static RENEWING: AtomicUsize = AtomicUsize::new(0);
+
+/// Importance of a timed function, which determines whether it may use reserved libsets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Priority {
+	/// May only use libsets that aren't reserved.
+	Normal,
+	/// May use any libset, including reserved ones.
+	High,
+}
+
+impl Default for Priority {
+	fn default() -> Self {
+		Priority::Normal
+	}
+}
+
+/// Snapshot of how the process's libsets are being used.  The four counts sum to the total
+/// number of libsets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Libsets {
+	/// Available to any launch.
+	pub free: usize,
+	/// Available, but only to high-priority launches.
+	pub reserved: usize,
+	/// Held by timed functions that haven't yet completed or been dropped.
+	pub in_use: usize,
+	/// Left behind by canceled timed functions and awaiting reinitialization.
+	pub renewing: usize,
+}
+
+/// Report how many libsets are free, reserved, and in use.
+pub fn libsets() -> Libsets {
+	use crate::tunables::reserved_libsets;
+
+	let renewing = RENEWING.load(Ordering::Relaxed);
+	let available = available(slots().pooled());
+	let reserved = reserved_libsets().min(available);
+	Libsets {
+		free: available - reserved,
+		reserved,
+		in_use: GotchaGroup::limit().saturating_sub(available + renewing),
+		renewing,
+	}
+}
+
+/// Take a slot if one is available, preferring one that last ran where the caller is running.
+/// Slots still being renewed don't count as available; callers that would rather wait for them
+/// should use `await_slot()` or `queue_slot()` instead.
+pub fn assign_slot(priority: Priority) -> SyncResult<'static, Slot> {
+	use crate::slots::Affinity;
+
+	// Look ourselves up before taking the lock, rather than once per idle slot while holding it.
+	let here = Affinity::current();
+	slots().try_take_if(admit(priority), |slot| slot.is_local(here))
+}
+
+/// Reinitialize the libset of `slot` after its timed function was canceled, returning the slot to
+/// the pool once it is clean.  This happens on a background thread if we can start one, so the
+/// caller need not wait for it.
+pub fn renew_slot(slot: ReusableSync<'static, Slot>) {
+	RENEWING.fetch_add(1, Ordering::Relaxed);
+	if let Some(renewer) = renewer() {
+		let renewer = renewer.lock().unwrap_or_else(|poison| poison.into_inner());
+		if let Err(slot) = renewer.send(slot) {
+			renew(slot.0);
+		}
+	} else {
+		renew(slot);
+	}
+}
+
+fn renew(slot: ReusableSync<'static, Slot>) {
+	use crate::abort;
+
+	if ! slot.group().renew() {
+		abort("libinger: failed to reinitialize library group");
+	}
+
+	// Only stop counting the slot once it's back in the pool.
+	drop(slot);
+	RENEWING.fetch_sub(1, Ordering::Relaxed);
+}
+
+fn renewer() -> Option<&'static Mutex<Sender<ReusableSync<'static, Slot>>>> {
+	use std::sync::Once;
+	use std::sync::mpsc::channel;
+	use std::thread::Builder;
+
+	static mut RENEWER: Option<Mutex<Sender<ReusableSync<'static, Slot>>>> = None;
+	static INIT: Once = Once::new();
+	INIT.call_once(|| {
+		let (renewer, renewals) = channel();
+		let thread = Builder::new().name("libinger-renew".into()).spawn(move ||
+			for slot in renewals {
+				renew(slot);
+			}
+		);
+
+		// If we can't start the thread, callers will have to renew synchronously.
+		if thread.is_ok() {
+			unsafe {
+				RENEWER.replace(Mutex::new(renewer));
+			}
+		}
+	});
+
+	unsafe {
+		RENEWER.as_ref()
+	}
+}
+
+/// Like `assign_slot()`, but wait up to `timeout` (or indefinitely) for a slot to free up.
+pub fn await_slot(timeout: Option<Duration>, priority: Priority) -> SyncResult<'static, Slot> {
+	slots().wait(timeout, admit(priority))
+}
+
+/// Get in line for the next available slot, which the returned future will resolve to.
+pub fn queue_slot(priority: Priority) -> QueuedSlot {
+	QueuedSlot (slots().enqueue(admit(priority)))
+}
+
+pub struct QueuedSlot (Ticket<'static, Slot, fn() -> Option<Slot>>);
+
+impl Future for QueuedSlot {
+	type Output = SyncResult<'static, Slot>;
+
+	fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+		let Self (ticket) = &mut *self;
+		if let Some(slot) = ticket.poll(Some(context.waker())) {
+			Poll::Ready(slot)
+		} else {
+			Poll::Pending
+		}
+	}
+}
+
+/// Number of callers waiting for a slot to free up.
+pub fn slot_waiters() -> usize {
+	slots().waiters()
+}
+
+/// Free the thread-control blocks and stacks of idle slots until at most `keep` of them still
+/// have theirs, returning how many resources were freed.
+pub fn trim_slots(keep: usize) -> usize {
+	use crate::slots::filled_slots;
+
+	let mut excess = filled_slots().saturating_sub(keep);
+	let mut freed = 0;
+	slots().inspect(|slot| if excess != 0 {
+		let hollowed = slot.hollow();
+		if hollowed != 0 {
+			freed += hollowed;
+			excess -= 1;
+		}
+	});
+	freed
+}
+
+fn admit(priority: Priority) -> fn(usize) -> bool {
+	fn any(_: usize) -> bool {
+		true
+	}
+
+	fn unreserved(pooled: usize) -> bool {
+		use crate::tunables::reserved_libsets;
+
+		available(pooled) > reserved_libsets()
+	}
+
+	match priority {
+	Priority::Normal => unreserved,
+	Priority::High => any,
+	}
+}
+
+/// Number of groups not in use, given how many are sitting in the pool.
+fn available(pooled: usize) -> usize {
+	pooled + GotchaGroup::limit().saturating_sub(CREATED.load(Ordering::Relaxed))
+}
+
+// Unlike the stack pools, this one stays behind a lock: waiters are served in FIFO order and
+// admission checks must be atomic with respect to taking a slot.  Slots are never destroyed, since
+// their libsets can't be.
+fn slots() -> &'static SyncPool<Slot> {
 	use crate::compile_assert::assert_sync;
-	use crate::reusable::SyncPool;
 
-	use std::convert::TryInto;
 	use std::sync::Once;
 
-	static mut GROUPS: Option<SyncPool<GotchaGroup>> = None;
+	fn create() -> Option<Slot> {
+		let slot = Slot::new();
+		if slot.is_some() {
+			CREATED.fetch_add(1, Ordering::Relaxed);
+		}
+		slot
+	}
+
+	static mut SLOTS: Option<SyncPool<Slot>> = None;
 	static INIT: Once = Once::new();
 	INIT.call_once(|| unsafe {
-		GROUPS.replace(SyncPool::new(GotchaGroup::new));
+		SLOTS.replace(SyncPool::new(create as _).with_recycler(Slot::recycle));
 	});
 
-	let groups = unsafe {
-		GROUPS.as_ref()
+	let slots = unsafe {
+		SLOTS.as_ref()
 	}.unwrap();
-	assert_sync(&groups);
-	groups.try_into()
+	assert_sync(&slots);
+	slots
 }
@@ -1,20 +1,24 @@
 mod compile_assert;
+mod error;
 pub mod ffi;
 pub mod force;
 pub mod future;
 mod groups;
 mod lifetime;
 mod linger;
-mod localstores;
 mod preemption;
 pub mod profiler;
 mod reusable;
 mod signals;
+mod slots;
 mod stacks;
+mod stats;
 #[cfg(not(feature = "notls"))]
 mod tcb;
 mod timer;
+mod tunables;
 mod unfurl;
+mod unwind;
 
 #[cfg(feature = "notls")]
 mod tcbstub;
@@ -23,7 +27,15 @@ mod tcb {
 	pub use crate::tcbstub::*;
 }
 
+pub use error::Error;
+pub use groups::Libsets;
+pub use groups::Priority;
+pub use groups::libsets;
 pub use linger::*;
+pub use slots::Affinity;
+pub use stats::Stats;
+pub use stats::stats;
+pub use tunables::*;
 
 use gotcha::Group;
 
@@ -36,9 +48,23 @@ const STACK_SIZE_BYTES: usize = 2 * 1_024 * 1_024;
 #[no_mangle]
 static libgotcha_exitanalysis: bool = true;
 
+/// The total number of timed functions that may exist at once.  For how many more could be
+/// launched right now, see `stats()`.
 pub fn concurrency_limit() -> usize {
 	Group::limit()
 }
 
+/// Free every idle stack and thread-control block, returning how many resources were released.
+///
+/// This lets a long-running process give memory back after a spike in load, at the cost of
+/// having to allocate these again for subsequent launches.  Idle libsets are kept, since they
+/// can never be recreated once destroyed.
+pub fn trim_caches() -> usize {
+	use groups::trim_slots;
+	use stacks::trim_stacks;
+
+	trim_stacks(0) + trim_slots(0)
+}
+
 #[cfg(test)]
 fn main() {}
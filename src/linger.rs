@@ -1,22 +1,41 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::groups::Priority;
 use crate::preemption::RealThreadId;
 use crate::preemption::defer_preemption;
 use crate::preemption::disable_preemption;
 use crate::preemption::is_preemptible;
 use crate::preemption::thread_signal;
 use crate::reusable::ReusableSync;
-use crate::stacks::DerefAdapter;
-use crate::tcb::ThreadControlBlock;
+use crate::slots::Affinity;
+use crate::slots::Slot;
+use crate::stacks::StackRef;
+use crate::timer::Clock;
+use crate::unwind::Raise;
 
 use gotcha::Group;
 use signal::Set;
 use signal::Signal;
 use signal::siginfo_t;
+use std::any::Any;
 use std::cell::Cell;
 use std::cell::RefCell;
-use std::io::Result;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::future::Future;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
+use std::result::Result as StdResult;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::thread::Result as ThdResult;
+use std::time::Duration;
+use std::time::Instant;
 use timetravel::errno::errno;
 use timetravel::Context;
 use timetravel::HandlerContext;
@@ -26,6 +45,25 @@ pub enum Linger<T, F: FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized> {
 	Completion(T),
 	Continuation(Continuation<F>),
 	Poison,
+	/// The timed function ran past the end of its stack and was abandoned.
+	Overflowed,
+	/// The timed function was canceled by `cancel_unwinding()`, which unwound its stack, or
+	/// abruptly along with the timed function that launched it.
+	Canceled,
+	/// The timed function panicked while being run by `try_launch()` or `try_resume()`, which
+	/// captured the panic's payload instead of propagating it.
+	Panicked(Box<dyn Any + Send>),
+}
+
+/// Why a timed function was abandoned without producing a value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PoisonReason {
+	/// It panicked, and the panic was propagated to whoever last launched or resumed it.
+	Panic,
+	/// It ran past the end of its stack and was canceled.
+	StackOverflow,
+	/// It was canceled by `cancel_unwinding()`, which unwound its stack.
+	Canceled,
 }
 
 impl<T, F: FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized> Unpin for Linger<T, F> {}
@@ -47,6 +85,37 @@ impl<T, F: FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized> Linger<T, F> {
 		}
 	}
 
+	/// Why the timed function was abandoned, or `None` unless it was.
+	pub fn poisoned(&self) -> Option<PoisonReason> {
+		match self {
+		Linger::Poison => Some(PoisonReason::Panic),
+		Linger::Overflowed => Some(PoisonReason::StackOverflow),
+		Linger::Canceled => Some(PoisonReason::Canceled),
+		Linger::Panicked(_) => Some(PoisonReason::Panic),
+		_ => None,
+		}
+	}
+
+	/// Where the timed function last ran, as a hint for where to resume it.  Returns `None`
+	/// unless this is a continuation that has run at least once.
+	pub fn affinity(&self) -> Option<Affinity> {
+		if let Linger::Continuation(continuation) = self {
+			held(&continuation.claim).slot.as_ref().and_then(|slot| slot.affinity())
+		} else {
+			None
+		}
+	}
+
+	/// A token through which the caller can ask the timed function to stop at its next
+	/// `checkpoint()`.  Returns `None` unless this is a continuation.
+	pub fn cancellation_token(&self) -> Option<CancellationToken> {
+		if let Linger::Continuation(continuation) = self {
+			Some(continuation.token.clone())
+		} else {
+			None
+		}
+	}
+
 	pub fn yielded(&self) -> bool {
 		if let Linger::Continuation(continuation) = self {
 			continuation.stateful.yielded
@@ -54,6 +123,42 @@ impl<T, F: FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized> Linger<T, F> {
 			false
 		}
 	}
+
+	/// How much time the timed function has spent running so far, as measured by its budget
+	/// clock.  Returns `None` unless this is a continuation.
+	pub fn consumed(&self) -> Option<Duration> {
+		self.usage().map(|usage| usage.runtime)
+	}
+
+	/// Counters describing the timed function's execution across all its runs so far.  Returns
+	/// `None` unless this is a continuation.
+	pub fn usage(&self) -> Option<Usage> {
+		if let Linger::Continuation(continuation) = self {
+			Some(continuation.meter.usage)
+		} else {
+			None
+		}
+	}
+
+	/// How much longer the timed function may run before reaching the deadline it was given by
+	/// its most recent launch or resumption, as measured by its budget clock.  Returns `None`
+	/// unless this is a continuation.
+	pub fn remaining(&self) -> Option<Budget> {
+		if let Linger::Continuation(continuation) = self {
+			let Meter {clock, deadline, left, ..} = continuation.meter;
+			Some(if deadline == u64::max_value() {
+				Budget::Unlimited
+			} else if clock == BudgetClock::ThreadCpu {
+				// Our own thread's CPU clock says nothing about the function's, which
+				// stood still from the moment it was paused.
+				Budget::Timeout(Duration::from_nanos(left))
+			} else {
+				Budget::Timeout(Duration::from_nanos(deadline.saturating_sub(clock.now())))
+			})
+		} else {
+			None
+		}
+	}
 }
 
 impl<'a, T, F: FnMut(*mut Option<ThdResult<T>>) + Send + 'a> Linger<T, F> {
@@ -63,26 +168,35 @@ impl<'a, T, F: FnMut(*mut Option<ThdResult<T>>) + Send + 'a> Linger<T, F> {
 	pub fn erase(self) -> Linger<T, dyn FnMut(*mut Option<ThdResult<T>>) + Send + 'a> {
 		use std::mem::MaybeUninit;
 
-		if let Linger::Completion(this) = self {
-			Linger::Completion(this)
-		} else if let Linger::Continuation(this) = self {
+		match self {
+		Linger::Completion(this) => Linger::Completion(this),
+		Linger::Continuation(this) => {
 			let this = MaybeUninit::new(this);
 			let this = this.as_ptr();
 			unsafe {
 				let functional: *const _ = &(*this).functional;
 				let stateful: *const _ = &(*this).stateful;
-				let group: *const _ = &(*this).group;
-				let tls: *const _ = &(*this).tls;
+				let claim: *const _ = &(*this).claim;
+				let meter: *const _ = &(*this).meter;
+				let canceling: *const _ = &(*this).canceling;
+				let raise: *const _ = &(*this).raise;
+				let token: *const _ = &(*this).token;
 
 				Linger::Continuation(Continuation {
 					functional: functional.read(),
 					stateful: stateful.read(),
-					group: group.read(),
-					tls: tls.read(),
+					claim: claim.read(),
+					meter: meter.read(),
+					canceling: canceling.read(),
+					raise: raise.read(),
+					token: token.read(),
 				})
 			}
-		} else {
-			Linger::Poison
+		},
+		Linger::Poison => Linger::Poison,
+		Linger::Overflowed => Linger::Overflowed,
+		Linger::Canceled => Linger::Canceled,
+		Linger::Panicked(panic) => Linger::Panicked(panic),
 		}
 	}
 }
@@ -98,24 +212,128 @@ pub struct Continuation<T: ?Sized> {
 	// resumption, we must heap allocate it so its captured environment has a stable address.
 	functional: Box<T>,
 	stateful: Task,
-	group: ReusableSync<'static, Group>,
-	tls: ReusableSync<'static, Option<ThreadControlBlock>>,
+	claim: Arc<Mutex<Claim>>,
+	meter: Meter,
+	// Set by cancel_unwinding() until the function starts unwinding.
+	canceling: Option<Raise>,
+	raise: Raise,
+	token: CancellationToken,
 }
 
 unsafe impl<T: ?Sized> Send for Continuation<T> {}
 
 impl<T: ?Sized> Drop for Continuation<T> {
 	fn drop(&mut self) {
-		debug_assert!(! is_preemptible(), "libinger: dropped from preemptible function");
+		use crate::stats::Event;
+		use crate::stats::record;
+
+		use std::mem::take;
+
+		debug_assert!(! is_running(&self.claim), "libinger: dropped a running timed function");
+		debug_assert!(
+			! is_preemptible() || is_nested(),
+			"libinger: dropped from preemptible code outside any timed function",
+		);
+
+		// The checkpoint refers to the slot's stack, so it mustn't outlive our claim to it.
+		drop(self.stateful.checkpoint.take());
+		let (slot, nested) = {
+			let mut claim = held(&self.claim);
+			(claim.slot.take(), take(&mut claim.nested))
+		};
+
+		// If our slot was reclaimed, abandon() already counted us as dropped and canceled.
+		let reclaimed = slot.is_none();
+		if ! reclaimed {
+			record(Event::Drop);
+		}
 
-		let group = &self.group;
 		if self.stateful.errno.is_some() {
-			// We're canceling a paused preemptible function.  Clean up the group!
-			assert!(group.renew(), "libinger: failed to reinitialize library group");
+			use crate::groups::renew_slot;
+
+			use std::panic::AssertUnwindSafe;
+			use std::panic::catch_unwind;
+
+			// An overflowed function was already counted when it was abandoned.
+			if ! self.stateful.overflowed && ! reclaimed {
+				record(Event::Cancellation);
+			}
+
+			// We're canceling a paused preemptible function.  Give it a chance to release
+			// anything it's holding onto, most recently registered first...
+			while let Some(cleanup) = self.stateful.cleanups.pop() {
+				if catch_unwind(AssertUnwindSafe(cleanup)).is_err() {
+					eprintln!("libinger: cancellation hook panicked");
+				}
+			}
+
+			// ...then clean up the group, along with those of any functions it launched that
+			// are still paused on its stack and will therefore never be resumed or dropped!
+			abandon(nested);
+			if let Some(slot) = slot {
+				renew_slot(slot);
+			}
 		}
 	}
 }
 
+/// A continuation's claim to its slot, which it shares with the timed function that launched it
+/// (if any) so the latter can reclaim it if canceled.
+#[derive(Default)]
+struct Claim {
+	// Present unless the function is running or has been reclaimed.
+	slot: Option<ReusableSync<'static, Slot>>,
+	// Claims of the functions launched from within this one.
+	nested: Vec<Weak<Mutex<Claim>>>,
+}
+
+fn held(claim: &Mutex<Claim>) -> MutexGuard<'_, Claim> {
+	claim.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// Renew the slots of the functions launched from within a canceled one, and of any they launched
+/// in turn.  Since they'll likely never be dropped, count them as dropped and canceled now.
+fn abandon(nested: Vec<Weak<Mutex<Claim>>>) {
+	use crate::groups::renew_slot;
+	use crate::stats::Event;
+	use crate::stats::record;
+
+	use std::mem::take;
+
+	for claim in nested.iter().filter_map(Weak::upgrade) {
+		let (slot, nested) = {
+			let mut claim = held(&claim);
+			(claim.slot.take(), take(&mut claim.nested))
+		};
+		abandon(nested);
+		if let Some(slot) = slot {
+			record(Event::Drop);
+			record(Event::Cancellation);
+			renew_slot(slot);
+		}
+	}
+}
+
+/// A timed function running on some kernel thread, as recorded in that thread's context stack.
+pub(crate) struct Frame {
+	clock: BudgetClock,
+	// Absolute deadline on the clock, as of the last time stamp() computed it.
+	deadline: u64,
+	claim: Arc<Mutex<Claim>>,
+}
+
+/// Whether the timed function holding `claim` is running on this kernel thread.
+#[inline(never)]
+fn is_running(claim: &Arc<Mutex<Claim>>) -> bool {
+	RealThreadId::current().contexts().borrow().iter().any(|frame| Arc::ptr_eq(&frame.claim, claim))
+}
+
+/// Whether the calling code is running inside a timed function on this kernel thread.
+#[inline(never)]
+fn is_nested() -> bool {
+	! RealThreadId::current().contexts().borrow().is_empty()
+}
+
 #[derive(Default)]
 struct Task {
 	// Also indicates the state of execution.  If we have a Continuation instance, we know the
@@ -124,8 +342,113 @@ struct Task {
 	//  * None at end of resume() means it has completed.
 	//  * Some at either point means it timed out and is currently paused.
 	errno: Option<c_int>,
-	checkpoint: Option<Context<DerefAdapter<'static, ReusableSync<'static, Box<[u8]>>>>>,
+	checkpoint: Option<Context<StackRef>>,
 	yielded: bool,
+	overflowed: bool,
+	cleanups: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+/// Clock against which a timed function's budget is measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BudgetClock {
+	/// Wall-clock time, which jumps whenever the system time is adjusted.
+	Wall,
+	/// Time elapsed since an arbitrary starting point, which never jumps.  This is the default.
+	Monotonic,
+	/// CPU time consumed by the kernel thread running the timed function, which doesn't advance
+	/// while the thread is descheduled or blocked in the kernel.  Each thread has its own such
+	/// clock, so a deadline on it means nothing once the function moves to another thread.
+	ThreadCpu,
+}
+
+impl BudgetClock {
+	/// Read this clock's current time, in nanoseconds.
+	fn now(self) -> u64 {
+		use crate::timer::clock_gettime;
+
+		let now = clock_gettime(self.into()).unwrap_or_else(|or|
+			abort(&format!("libinger: clock error: {}", or))
+		);
+		let mut sum = now.tv_nsec as u64;
+		sum += now.tv_sec as u64 * 1_000_000_000;
+		sum
+	}
+}
+
+impl Default for BudgetClock {
+	fn default() -> Self {
+		BudgetClock::Monotonic
+	}
+}
+
+impl From<BudgetClock> for Clock {
+	fn from(clock: BudgetClock) -> Self {
+		match clock {
+		BudgetClock::Wall => Clock::Real,
+		BudgetClock::Monotonic => Clock::Mono,
+		BudgetClock::ThreadCpu => Clock::Thread,
+		}
+	}
+}
+
+/// How long a timed function may run before it is paused.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Budget {
+	/// Don't run the timed function at all: just initialize it (if launching).
+	SetupOnly,
+	/// Run for at most this long.  Timeouts too long to represent in nanoseconds are unlimited.
+	Timeout(Duration),
+	/// Run until this point in time.  For budget clocks other than the monotonic one, it is
+	/// converted to a timeout when the function is launched or resumed.
+	Deadline(Instant),
+	/// Run to completion.
+	Unlimited,
+}
+
+impl Budget {
+	/// Interpret a budget in microseconds, where `0` and `max_value()` are sentinels.
+	fn from_us(us: u64) -> Self {
+		match us {
+		0 => Budget::SetupOnly,
+		us if us == u64::max_value() => Budget::Unlimited,
+		us => Budget::Timeout(Duration::from_micros(us)),
+		}
+	}
+
+	/// Convert to a timeout in nanoseconds from now, where `max_value()` means unlimited.
+	fn timeout(self) -> u64 {
+		match self {
+		Budget::SetupOnly => 0,
+		Budget::Timeout(timeout) => nanos(timeout),
+		Budget::Deadline(deadline) => {
+			let now = Instant::now();
+			if deadline > now {
+				nanos(deadline - now)
+			} else {
+				0
+			}
+		},
+		Budget::Unlimited => u64::max_value(),
+		}
+	}
+}
+
+fn nanos(duration: Duration) -> u64 {
+	use std::convert::TryInto;
+
+	duration.as_nanos().try_into().unwrap_or(u64::max_value())
+}
+
+impl From<Duration> for Budget {
+	fn from(timeout: Duration) -> Self {
+		Budget::Timeout(timeout)
+	}
+}
+
+impl From<Instant> for Budget {
+	fn from(deadline: Instant) -> Self {
+		Budget::Deadline(deadline)
+	}
 }
 
 /// Run `fun` with the specified time budget, in `us`econds.
@@ -134,8 +457,124 @@ struct Task {
 /// it is run to completion.
 pub fn launch<T: Send>(fun: impl FnOnce() -> T + Send, us: u64)
 -> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
-	use crate::localstores::alloc_localstore;
-	use crate::groups::assign_group;
+	launch_on(fun, Budget::from_us(us), BudgetClock::default())
+}
+
+/// Run `fun` with the specified time budget, which may be a `Duration`, an `Instant`, or a
+/// `Budget`.
+pub fn launch_for<T: Send>(fun: impl FnOnce() -> T + Send, budget: impl Into<Budget>)
+-> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	launch_on(fun, budget, BudgetClock::default())
+}
+
+/// Run `fun` until the specified `deadline`, as measured by the monotonic clock.
+pub fn launch_until<T: Send>(fun: impl FnOnce() -> T + Send, deadline: Instant)
+-> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	launch_on(fun, deadline, BudgetClock::Monotonic)
+}
+
+/// Run `fun` with the specified time budget, as measured by `clock`.
+///
+/// The timed function remembers the clock, so later calls to `resume()` measure against it too.
+pub fn launch_on<T: Send>(fun: impl FnOnce() -> T + Send, budget: impl Into<Budget>, clock: BudgetClock)
+-> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	launch_with(fun, budget, LaunchOptions {
+		clock,
+		..LaunchOptions::default()
+	})
+}
+
+/// Settings for launching a timed function, other than its time budget.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LaunchOptions {
+	/// Clock against which the budget is measured.  The timed function remembers it, so later
+	/// calls to `resume()` measure against it too.
+	pub clock: BudgetClock,
+	/// Minimum size of the execution stack, in bytes.  Stacks come from pools of power-of-two
+	/// size classes ranging from 64 KiB to 1 GiB; requesting a larger stack is an error.
+	pub stack: usize,
+	/// High-priority launches may use the libsets held back by `set_reserved_libsets()`.
+	pub priority: Priority,
+}
+
+impl Default for LaunchOptions {
+	fn default() -> Self {
+		use super::STACK_SIZE_BYTES;
+
+		Self {
+			clock: BudgetClock::default(),
+			stack: STACK_SIZE_BYTES,
+			priority: Priority::default(),
+		}
+	}
+}
+
+/// Run `fun` with the specified time budget and `options`.  Fails immediately if no libset is
+/// free, including while those of canceled functions are still being renewed; see
+/// `launch_blocking()` to wait instead.
+pub fn launch_with<T: Send>(
+	fun: impl FnOnce() -> T + Send,
+	budget: impl Into<Budget>,
+	options: LaunchOptions,
+) -> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	use crate::groups::assign_slot;
+
+	let slot = assign_slot(options.priority).map_err(|_| Error::NoLibset)?;
+	launch_slot(fun, budget.into(), options, slot)
+}
+
+/// Run `fun` with the specified time budget and `options`, first waiting up to `timeout` (or
+/// indefinitely if `None`) for a libset to become available if all of them are in use.
+///
+/// Callers that have to wait are admitted in the order they arrived, except that high-priority
+/// ones may pass normal-priority ones while only reserved libsets are free.
+pub fn launch_blocking<T: Send>(
+	fun: impl FnOnce() -> T + Send,
+	budget: impl Into<Budget>,
+	options: LaunchOptions,
+	timeout: Option<Duration>,
+) -> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	use crate::groups::await_slot;
+
+	let slot = await_slot(timeout, options.priority).map_err(|_| Error::NoLibset)?;
+	launch_slot(fun, budget.into(), options, slot)
+}
+
+/// Run `fun` with the specified time budget and `options` once a libset is available, without
+/// blocking the calling thread in the meantime.
+///
+/// Callers are admitted in the order they called this function, even if they start awaiting the
+/// resulting future later.
+pub fn launch_async<T: Send>(
+	fun: impl FnOnce() -> T + Send,
+	budget: impl Into<Budget>,
+	options: LaunchOptions,
+) -> impl Future<Output = Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>>> {
+	use crate::groups::queue_slot;
+
+	let budget = budget.into();
+	let slot = queue_slot(options.priority);
+	async move {
+		let slot = slot.await.map_err(|_| Error::NoLibset)?;
+		launch_slot(fun, budget, options, slot)
+	}
+}
+
+/// Number of callers currently waiting in `launch_blocking()` or `launch_async()` for a libset.
+pub fn launch_queue_depth() -> usize {
+	use crate::groups::slot_waiters;
+
+	slot_waiters()
+}
+
+fn launch_slot<T: Send>(
+	fun: impl FnOnce() -> T + Send,
+	budget: Budget,
+	options: LaunchOptions,
+	mut slot: ReusableSync<'static, Slot>,
+) -> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	use crate::stats::Event;
+	use crate::stats::record;
 
 	use std::panic::AssertUnwindSafe;
 	use std::panic::catch_unwind;
@@ -158,9 +597,15 @@ pub fn launch<T: Send>(fun: impl FnOnce() -> T + Send, us: u64)
 	// exported parameterized functions are actually monomorphized into the *caller's* object
 	// file!  This means that this function has an inconsistent view of the worldstate if called
 	// from a preemptible function, but that any non-generic (name brand?) functions it calls
-	// are guaranteed to run in the libgotcha's shared group.  To guard against heisenbugs
-	// arising from the former case, we first assert that no one has attempted a nested call.
-	debug_assert!(! is_preemptible(), "launch(): called from preemptible function");
+	// are guaranteed to run in the libgotcha's shared group.  To support nested calls, we must
+	// therefore leave all access to global and thread-local state to the latter.
+	debug_assert!(
+		! is_preemptible() || is_nested(),
+		"launch(): called from preemptible code outside any timed function",
+	);
+
+	let LaunchOptions {clock, stack, ..} = options;
+	slot.prepare(stack)?;
 
 	let mut fun = Completion::Function(AssertUnwindSafe (fun));
 	let fun = Box::new(move |ret: *mut Option<ThdResult<T>>| {
@@ -182,16 +627,23 @@ pub fn launch<T: Send>(fun: impl FnOnce() -> T + Send, us: u64)
 		}
 	});
 
-	let group = assign_group().expect("launch(): too many active timed functions");
 	let mut linger = Linger::Continuation(Continuation {
 		functional: fun,
 		stateful: Task::default(),
-		group,
-		tls: alloc_localstore(),
+		claim: Arc::new(Mutex::new(Claim {
+			slot: Some(slot),
+			..Claim::default()
+		})),
+		meter: Meter {
+			clock,
+			..Meter::default()
+		},
+		canceling: None,
+		raise: raise::<T>,
+		token: CancellationToken::default(),
 	});
-	if us != 0 {
-		resume(&mut linger, us)?;
-	}
+	record(Event::Launch);
+	resume_on(&mut linger, budget, clock)?;
 	Ok(linger)
 }
 
@@ -200,65 +652,257 @@ thread_local! {
 	static BOOTSTRAP: Cell<Option<(NonNull<(dyn FnMut() + Send)>, Group)>> = Cell::default();
 	static TASK: RefCell<Task> = RefCell::default();
 	static DEADLINE: Cell<u64> = Cell::default();
+	static TIMEOUT: Cell<Option<u64>> = Cell::default();
+	static CLOCK: Cell<BudgetClock> = Cell::default();
+	static STAMP: Cell<(u64, u64)> = Cell::default();
+
+	// When preemption was most recently deferred (or 0 if it isn't), and the total time it has
+	// spent deferred during the current run.
+	static DEFERRAL: Cell<(u64, u64)> = Cell::default();
+
+	// Bounds of the guard region beneath the running timed function's stack.
+	static GUARD: Cell<(usize, usize)> = Cell::default();
+
+	// How to unwind the running timed function if it is being canceled by cancel_unwinding().
+	static CANCELING: Cell<Option<Raise>> = Cell::default();
+
+	// Lets the running timed function check whether its caller wants it to stop.
+	static TOKEN: RefCell<Option<CancellationToken>> = RefCell::default();
+}
+
+/// Execution counters for a timed function, accumulated across all its runs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Usage {
+	/// Total time spent running, as measured by the budget clock.
+	pub runtime: Duration,
+	/// Number of times the function has been launched or resumed.
+	pub resumptions: u64,
+	/// Number of times the function was paused because it exhausted its budget.
+	pub preemptions: u64,
+	/// Number of times the function paused itself by calling `pause()`.
+	pub yields: u64,
+	/// Total time preemption was postponed because the function was in a nonpreemptible
+	/// library call when its budget ran out, as measured by the budget clock.
+	pub deferred: Duration,
+}
+
+/// Accounting for a timed function's budget, in nanoseconds on its budget clock.
+#[derive(Default)]
+struct Meter {
+	clock: BudgetClock,
+	deadline: u64,
+	// How far the function was from its deadline when it last stopped running.
+	left: u64,
+	usage: Usage,
+}
+
+impl Meter {
+	/// Prepare to run the timed function with the specified `budget`, as measured by `clock`.
+	/// Call after installing a virtual TCB!
+	#[inline(never)]
+	fn start(&mut self, budget: Budget, clock: BudgetClock) {
+		self.clock = clock;
+
+		// Deadlines on the monotonic clock are already absolute, so don't let stamp() convert
+		// them.  Anything else is relative to whenever the function actually starts running.
+		let (deadline, timeout) = match budget {
+		Budget::Deadline(deadline) if clock == BudgetClock::Monotonic =>
+			(monotonic(deadline), None),
+		budget =>
+			(u64::max_value(), Some(budget.timeout())),
+		};
+		DEADLINE.with(|absolute| absolute.replace(deadline));
+		TIMEOUT.with(|relative| relative.replace(timeout));
+		CLOCK.with(|current| current.replace(clock));
+		DEFERRAL.with(|deferral| deferral.take());
+		self.usage.resumptions += 1;
+	}
+
+	/// Record the timed function's usage once it has returned control to us.  Call before
+	/// uninstalling the virtual TCB!
+	#[inline(never)]
+	fn stop(&mut self, finished: bool, yielded: bool) {
+		let now = self.clock.now();
+		let (started, deadline) = STAMP.with(|stamp| stamp.get());
+		self.deadline = deadline;
+		self.left = deadline.saturating_sub(now);
+		self.usage.runtime += Duration::from_nanos(now.saturating_sub(started));
+
+		// The function may have finished while a preemption was still deferred.
+		let (since, deferred) = DEFERRAL.with(|deferral| deferral.take());
+		let deferred = if since != 0 {
+			deferred + now.saturating_sub(since)
+		} else {
+			deferred
+		};
+		self.usage.deferred += Duration::from_nanos(deferred);
+
+		if ! finished {
+			if yielded {
+				self.usage.yields += 1;
+			} else {
+				self.usage.preemptions += 1;
+			}
+		}
+	}
+}
+
+/// Convert an `Instant` to a point on the monotonic clock, in nanoseconds.
+fn monotonic(instant: Instant) -> u64 {
+	let now = Instant::now();
+	let ns = nsnow();
+	if instant > now {
+		ns.saturating_add(nanos(instant - now))
+	} else {
+		ns.saturating_sub(nanos(now - instant))
+	}
 }
 
 /// Let `fun` continue running for the specified time budget, in `us`econds.
 ///
-/// If the budget is `0`, this is a no-op; if it is `max_value()`, the timed function is run to
-/// completion.  This function is idempotent once the timed function completes.
+/// If the budget is `0`, the timed function only runs until the next time it is checked for
+/// preemption; if it is `max_value()`, it is run to completion.  This function is idempotent once
+/// the timed function completes.
+///
+/// A paused timed function may be resumed from any kernel thread, not just the one that launched
+/// it; `Linger::affinity()` reports where it last ran.  A deadline on `BudgetClock::ThreadCpu`
+/// doesn't carry over to another thread, so each resumption's budget is measured afresh by the
+/// CPU clock of whichever thread runs it.
 pub fn resume<T>(fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>, us: u64)
 -> Result<&mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>> {
+	// Unlike launch(), we've always given the function a (very) brief run when passed 0.
+	let budget = if us == 0 {
+		Budget::Timeout(Duration::default())
+	} else {
+		Budget::from_us(us)
+	};
+	resume_for(fun, budget)
+}
+
+/// Let `fun` continue running for the specified time budget, which may be a `Duration`, an
+/// `Instant`, or a `Budget`.
+pub fn resume_for<T>(
+	fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>,
+	budget: impl Into<Budget>,
+) -> Result<&mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>> {
+	let clock = if let Linger::Continuation(continuation) = fun {
+		continuation.meter.clock
+	} else {
+		BudgetClock::default()
+	};
+	resume_on(fun, budget.into(), clock)
+}
+
+/// Let `fun` continue running until the specified `deadline`.
+///
+/// Unlike `resume()`, this makes it easy to enforce a single deadline across many resumptions.
+/// The function keeps its own budget clock; unless that is the monotonic one, the deadline is
+/// converted to a timeout on it at the time of the call.
+pub fn resume_until<T>(
+	fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>,
+	deadline: Instant,
+) -> Result<&mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>> {
+	resume_for(fun, Budget::Deadline(deadline))
+}
+
+/// Let `fun` continue running for the specified time budget, as measured by `clock`.
+///
+/// The timed function remembers the clock, so later calls to `resume()` measure against it too.
+pub fn resume_on<T>(
+	fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>,
+	budget: impl Into<Budget>,
+	clock: BudgetClock,
+) -> Result<&mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>> {
+	resume_catching(fun, budget.into(), clock, false)?;
+	Ok(fun)
+}
+
+/// Run `fun` with the specified time budget and `options`.  If it panics, the panic is captured
+/// in a `Linger::Panicked` instead of being propagated to the caller.
+pub fn try_launch<T: Send>(
+	fun: impl FnOnce() -> T + Send,
+	budget: impl Into<Budget>,
+	options: LaunchOptions,
+) -> Result<Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send>> {
+	let mut fun = launch_with(fun, Budget::SetupOnly, options)?;
+	try_resume(&mut fun, budget)?;
+	Ok(fun)
+}
+
+/// Let `fun` continue running for the specified time budget, which may be a `Duration`, an
+/// `Instant`, or a `Budget`.  If it panics, the panic is captured in a `Linger::Panicked` instead
+/// of being propagated to the caller.
+pub fn try_resume<T>(
+	fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>,
+	budget: impl Into<Budget>,
+) -> Result<&mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>> {
+	let clock = if let Linger::Continuation(continuation) = fun {
+		continuation.meter.clock
+	} else {
+		BudgetClock::default()
+	};
+	resume_catching(fun, budget.into(), clock, true)?;
+	Ok(fun)
+}
+
+fn resume_catching<T, F: FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>(
+	fun: &mut Linger<T, F>,
+	budget: Budget,
+	clock: BudgetClock,
+	catch: bool,
+) -> Result<()> {
+	use crate::stats::Event;
+	use crate::stats::record;
+
 	use std::panic::resume_unwind;
 
 	// Danger, W.R!  The same disclaimer from launch() applies here.
-	debug_assert!(! is_preemptible(), "resume(): called from preemptible function");
+
+	if budget == Budget::SetupOnly {
+		return Ok(());
+	}
 
 	if let Linger::Continuation(continuation) = fun {
-		let task = &mut continuation.stateful;
-		let group = *continuation.group;
-		let thread = RealThreadId::current();
-
-		// Install the virtual thread-control block.  It is only safe to use thread-locals
-		// between the end of this block and when we uninstall it again!
-		let tls = continuation.tls.take().expect("libinger: continuation with missing TCB");
-		let tls = unsafe {
-			tls.install(group)?
+		debug_assert!(! is_running(&continuation.claim), "resume(): timed function is already running");
+		debug_assert!(
+			! is_preemptible() || is_nested(),
+			"resume(): called from preemptible code outside any timed function",
+		);
+
+		let slot = held(&continuation.claim).slot.take();
+		let mut slot = if let Some(slot) = slot {
+			slot
+		} else {
+			// The function that launched it was canceled, taking it along.
+			*fun = Linger::Canceled;
+			return Ok(());
 		};
 
-		if let Err(or) = setup_thread(thread) {
-			abort(&format!("resume(): failure in thread_setup(): {}", or));
-		}
-
-		// Are we launching this preemptible function for the first time?
-		if task.errno.is_none() {
-			let fun = &mut continuation.functional;
-			task.checkpoint = setup_stack()?;
-			BOOTSTRAP.with(|bootstrap| {
-				// The schedule() function is polymorphic across "return" types, but
-				// we expect a storage area appropriate for our own type.  To remove
-				// the specialization from our signature, we reduce our arity by
-				// casting away our parameter.  This is safe because schedule()
-				// represents our first caller, so we know not to read the argument.
-				let fun: *mut (dyn FnMut(_) + Send) = fun;
-				let fun: *mut (dyn FnMut() + Send) = fun as _;
-				let no_fun = bootstrap.replace(NonNull::new(fun).map(|fun|
-					(fun, group)
-				));
-				debug_assert!(
-					no_fun.is_none(),
-					"resume(): bootstraps without an intervening schedule()",
-				);
-			});
-		}
-
-		DEADLINE.with(|deadline| deadline.replace(us));
-
-		// Transfer control into the libinger module before running the function!
-		let finished = switch_stack(task, group)?;
-		continuation.tls.replace(unsafe {
-			tls.uninstall()?
-		});
-		if finished {
+		let finished = {
+			// The schedule() function is polymorphic across "return" types, but we expect
+			// a storage area appropriate for our own type.  To remove the specialization
+			// from our signature, we reduce our arity by casting away our parameter.  This
+			// is safe because schedule() represents our first caller, so we know not to
+			// read the argument.
+			let fun: *mut (dyn FnMut(_) + Send) = &mut continuation.functional;
+			let fun: *mut (dyn FnMut() + Send) = fun as _;
+			run(Job {
+				fun,
+				task: &mut continuation.stateful,
+				slot: &mut slot,
+				claim: &continuation.claim,
+				meter: &mut continuation.meter,
+				canceling: &mut continuation.canceling,
+				token: &continuation.token,
+			}, budget, clock)
+		};
+		held(&continuation.claim).slot.replace(slot);
+		let finished = finished?;
+		if continuation.stateful.overflowed {
+			// Dropping the continuation will clean up after the abandoned function.
+			record(Event::Overflow);
+			*fun = Linger::Overflowed;
+		} else if finished {
 			// The preemptible function finished (either ran to completion or panicked).
 			// Since we know the closure is no longer running concurrently, it's now
 			// safe to call it again to retrieve the return value.
@@ -266,37 +910,312 @@ pub fn resume<T>(fun: &mut Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Sen
 			(continuation.functional)(&mut retval);
 
 			match retval.expect("resume(): return value was already retrieved") {
-				Ok(retval) => *fun = Linger::Completion(retval),
-				Err(panic) => {
-					*fun = Linger::Poison;
-					resume_unwind(panic);
+				Ok(retval) => {
+					record(Event::Completion);
+					*fun = Linger::Completion(retval);
+				},
+				Err(panic) => if panic.is::<Cancellation>() {
+					record(Event::Cancellation);
+					*fun = Linger::Canceled;
+				} else {
+					record(Event::Panic);
+					if catch {
+						*fun = Linger::Panicked(panic);
+					} else {
+						*fun = Linger::Poison;
+						resume_unwind(panic);
+					}
 				},
 			}
 		}
 	}
 
-	Ok(fun)
+	Ok(())
+}
+
+/// The parts of a continuation that `run()` needs, stripped of its type.
+struct Job<'a> {
+	fun: *mut (dyn FnMut() + Send),
+	task: &'a mut Task,
+	slot: &'a mut Slot,
+	claim: &'a Arc<Mutex<Claim>>,
+	meter: &'a mut Meter,
+	canceling: &'a mut Option<Raise>,
+	token: &'a CancellationToken,
+}
+
+/// Run the timed function until it finishes or is paused, returning whether it finished.  Because
+/// this function is not parameterized, it always runs in the shared group, even when called from
+/// another timed function; in that case, the new one is nested inside it.
+#[inline(never)]
+fn run(job: Job, budget: Budget, clock: BudgetClock) -> Result<bool> {
+	use crate::preemption::resume_outer;
+	use crate::preemption::thread_unbind;
+	use crate::tunables::quantum;
+
+	let Job {fun, task, slot, claim, meter, canceling, token} = job;
+
+	// Look up the calling kernel thread before installing the TCB, which may have come from
+	// another one.  Setting up this thread below rebinds the function's preemption signal and
+	// timer to it, and switch_stack() restores the function's errno atop its signal mask.
+	let group = slot.group();
+	let thread = RealThreadId::current();
+	let contexts = thread.contexts();
+	let quantum = quantum();
+
+	// Install the virtual thread-control block.  It is only safe to use thread-locals between
+	// the end of this block and when we uninstall it again!
+	let tls = slot.take_tls().expect("libinger: continuation with missing TCB");
+	let tls = match unsafe {
+		tls.install(group)
+	} {
+	Ok(tls) => tls,
+	Err((or, tls)) => {
+		// Put the TCB back so the caller can try again later.
+		slot.replace_tls(tls);
+		return Err(Error::Tls(Some(or)));
+	},
+	};
+
+	// Are we launching this preemptible function for the first time?
+	let launching = task.errno.is_none();
+	let setup = setup_thread(thread, clock, quantum).and_then(|_|
+		if launching {
+			setup_stack(slot.stack()).map(Some)
+		} else {
+			Ok(None)
+		}
+	);
+	let setup = match setup {
+	Ok(setup) => setup,
+	Err(or) => {
+		// Leave the continuation intact so the caller can try again later.
+		thread_unbind();
+		slot.replace_tls(unsafe {
+			tls.uninstall()
+		}.map_err(|or| Error::Tls(Some(or)))?);
+		return Err(or);
+	},
+	};
+
+	if let Some(checkpoint) = setup {
+		task.checkpoint = checkpoint;
+
+		// If we're being launched from within another timed function, let it reclaim our
+		// slot should it be canceled while we're still paused on its stack.
+		if let Some(outer) = contexts.borrow().last() {
+			let mut outer = held(&outer.claim);
+			outer.nested.retain(|nested| nested.strong_count() != 0);
+			outer.nested.push(Arc::downgrade(claim));
+		}
+
+		BOOTSTRAP.with(|bootstrap| {
+			let no_fun = bootstrap.replace(NonNull::new(fun).map(|fun| (fun, group)));
+			debug_assert!(
+				no_fun.is_none(),
+				"resume(): bootstraps without an intervening schedule()",
+			);
+		});
+	}
+
+	meter.start(budget, clock);
+	let guard = slot.guard();
+	GUARD.with(|bounds| bounds.replace(guard));
+	CANCELING.with(|flag| flag.replace(*canceling));
+	TOKEN.with(|current| current.replace(Some(token.clone())));
+
+	// Transfer control into the libinger module before running the function!
+	contexts.borrow_mut().push(Frame {
+		clock,
+		deadline: u64::max_value(),
+		claim: claim.clone(),
+	});
+	let finished = switch_stack(task, group);
+	contexts.borrow_mut().pop();
+	if let Ok(finished) = finished {
+		meter.stop(finished, task.yielded);
+	}
+	if CANCELING.with(|flag| flag.take()).is_none() {
+		// It has started unwinding, so mustn't be made to start again.
+		canceling.take();
+	}
+	// The TCB goes back to the pool with the slot, so don't let the next occupant see our token.
+	TOKEN.with(|current| current.take());
+	// Nor let it refer to this kernel thread, which may exit before it runs again.
+	thread_unbind();
+	slot.replace_tls(unsafe {
+		tls.uninstall()
+	}.map_err(|or| Error::Tls(Some(or)))?);
+	slot.touch();
+
+	// Were we called from within another timed function?
+	let outer = contexts.borrow().last().map(|outer| (outer.clock, outer.deadline));
+	if let Some((clock, deadline)) = outer {
+		// The nested function disarmed the timer and left the preemption signal blocked, so
+		// hand them back to the enclosing one, which gets preempted as soon as we return if
+		// it has used up its own budget in the meantime.
+		resume_outer(clock.into(), deadline, clock.now() >= deadline)?;
+	}
+	finished
+}
+
+/// Cancel `fun`, first giving it up to `budget` to unwind its stack so the destructors of any
+/// values living there get to run.  Returns whether it managed to do so; if not, it is canceled
+/// abruptly, just as if it had been dropped.
+///
+/// The unwinding starts wherever the function is preempted next, beginning right where it left
+/// off.  Since most instructions are not valid places to begin unwinding, and since we can't
+/// unwind back out through a call into nonpreemptible code, the function keeps running in short
+/// slices until it is preempted somewhere that works or the budget runs out.  In particular, a
+/// function that only ever stops by calling `pause()` can't be unwound.  Neither can any function
+/// on platforms other than x86-64, nor while unwinding is disabled by `set_unwinding()`.  If the
+/// function panics of its own accord while running its slices, it counts as having finished.
+pub fn cancel_unwinding<T>(
+	mut fun: Linger<T, impl FnMut(*mut Option<ThdResult<T>>) + Send + ?Sized>,
+	budget: impl Into<Budget>,
+) -> bool {
+	use crate::tunables::quantum;
+	use crate::tunables::unwinding;
+
+	let clock = if let Linger::Continuation(continuation) = &mut fun {
+		if continuation.stateful.errno.is_none() {
+			// It never started running, so there's nothing on its stack.
+			return true;
+		}
+		if ! cfg!(target_arch = "x86_64") || ! unwinding() {
+			return false;
+		}
+		continuation.canceling.replace(continuation.raise);
+		continuation.meter.clock
+	} else {
+		BudgetClock::default()
+	};
+
+	let deadline = match budget.into() {
+	Budget::SetupOnly => Some(Instant::now()),
+	Budget::Timeout(timeout) => Instant::now().checked_add(timeout),
+	Budget::Deadline(deadline) => Some(deadline),
+	Budget::Unlimited => None,
+	};
+
+	// The first slice is empty, so that it is preempted exactly where it paused.
+	let mut slice = Duration::default();
+	while fun.is_continuation() {
+		let now = Instant::now();
+		let budget = match deadline {
+		Some(deadline) if now >= deadline => break,
+		Some(deadline) => slice.min(deadline - now),
+		None => slice,
+		};
+		if resume_catching(&mut fun, Budget::Timeout(budget), clock, true).is_err() {
+			break;
+		}
+		slice = Duration::from_micros(quantum());
+	}
+	! fun.is_continuation()
+}
+
+/// Arrange for `cleanup` to be called if the calling timed function is canceled while paused,
+/// e.g., to release resources it holds outside its own stack.  Hooks run in the reverse of the
+/// order they were registered, before the function's libset is reinitialized; they are discarded
+/// if the function runs to completion.  Must be called from within a timed function.
+pub fn on_cancel(cleanup: impl FnOnce() + Send + 'static) {
+	on_cancel_boxed(Box::new(cleanup));
+}
+
+#[doc(hidden)]
+#[inline(never)]
+pub fn on_cancel_boxed(cleanup: Box<dyn FnOnce() + Send>) {
+	TASK.with(|task| task.borrow_mut().cleanups.push(cleanup));
+}
+
+/// Indication that a timed function has been asked to stop.  This is also the panic payload with
+/// which `cancel_unwinding()` unwinds it.
+#[derive(Debug)]
+pub struct Cancellation;
+
+/// Start unwinding a timed function that is being canceled by `cancel_unwinding()`.  It's
+/// parameterized so it gets monomorphized alongside launch_slot()'s catch_unwind(), which must
+/// recognize the panic as its own.
+extern "C-unwind" fn raise<T>() -> ! {
+	use std::panic::resume_unwind;
+
+	resume_unwind(Box::new(Cancellation))
+}
+
+impl Display for Cancellation {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		write!(f, "timed function was canceled")
+	}
+}
+
+impl std::error::Error for Cancellation {}
+
+/// A flag by which a timed function's caller can ask it to wind down cooperatively, rather than
+/// abruptly canceling it by dropping it.  The function observes it via `is_canceled()` and
+/// `checkpoint()`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken (Arc<AtomicBool>);
+
+impl CancellationToken {
+	/// Ask the timed function to stop.  It will notice the next time it checks, which may not be
+	/// until it is next resumed.
+	pub fn cancel(&self) {
+		let Self (canceled) = self;
+		canceled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_canceled(&self) -> bool {
+		let Self (canceled) = self;
+		canceled.load(Ordering::Relaxed)
+	}
+}
+
+/// Whether the calling timed function's caller has asked it to stop.  Always false outside of a
+/// timed function.
+#[inline(never)]
+pub fn is_canceled() -> bool {
+	TOKEN.with(|token| token.borrow().as_ref().map(|token| token.is_canceled()).unwrap_or(false))
+}
+
+/// Same as `is_canceled()`, under the spelling by which it was first requested.
+pub fn is_cancelled() -> bool {
+	is_canceled()
+}
+
+/// Return an error if the calling timed function's caller has asked it to stop, so that it can
+/// wind down by propagating it with `?`.
+pub fn checkpoint() -> StdResult<(), Cancellation> {
+	if is_canceled() {
+		Err(Cancellation)
+	} else {
+		Ok(())
+	}
 }
 
 /// Set up preemption for a kernel execution thread.  Call after installing a virtual TCB!
 #[inline(never)]
-fn setup_thread(thread: RealThreadId) -> Result<()> {
+fn setup_thread(thread: RealThreadId, clock: BudgetClock, quantum: u64) -> Result<()> {
 	use crate::preemption::thread_setup;
-	use super::QUANTUM_MICROSECS;
-	thread_setup(thread, preempt, QUANTUM_MICROSECS)
+	use crate::signals::assign_fault_handler;
+	use crate::tunables::TimerMode;
+	use crate::tunables::timer_mode;
+
+	assign_fault_handler(overflow).map_err(Error::NoSignal)?;
+
+	// In one-shot mode, stamp() arms the timer once we know the deadline.
+	let quantum = if timer_mode() == TimerMode::Periodic { quantum } else { 0 };
+	thread_setup(thread, preempt, clock.into(), quantum).map_err(Error::NoSignal)
 }
 
 /// Set up the oneshot execution stack.  Always returns a Some when things are Ok.
 #[inline(never)]
-fn setup_stack()
--> Result<Option<Context<DerefAdapter<'static, ReusableSync<'static, Box<[u8]>>>>>> {
-	use crate::stacks::alloc_stack;
-
+fn setup_stack(stack: StackRef) -> Result<Option<Context<StackRef>>> {
 	use timetravel::makecontext;
 
 	let mut checkpoint = None;
 	makecontext(
-		DerefAdapter::from(alloc_stack()),
+		stack,
 		|goto| drop(checkpoint.replace(goto)),
 		schedule,
 	)?;
@@ -316,7 +1235,10 @@ fn switch_stack(task: &mut Task, group: Group) -> Result<bool> {
 	use timetravel::restorecontext;
 	use timetravel::sigsetcontext;
 
+	use std::mem::take;
+
 	let mut error = None;
+	let mut cleanups = Some(take(&mut task.cleanups));
 	restorecontext(
 		task.checkpoint.take().expect("switch_stack(): continuation is missing"),
 		|pause| {
@@ -327,6 +1249,11 @@ fn switch_stack(task: &mut Task, group: Group) -> Result<bool> {
 					"switch_stack(): this continuation would nest?!"
 				);
 
+				// Hand back any cancellation hooks it registered during earlier runs.
+				if let Some(cleanups) = cleanups.take() {
+					task.cleanups = cleanups;
+				}
+
 				let resume = task.checkpoint.get_or_insert(pause);
 				unsafe {
 					unbound_mut(resume)
@@ -416,9 +1343,14 @@ fn schedule() {
 }
 
 /// Signal handler that pauses the preemptible function on timeout.  Runs on the oneshot stack.
-extern fn preempt(no: Signal, _: Option<&siginfo_t>, uc: Option<&mut HandlerContext>) {
+extern fn preempt(no: Signal, info: Option<&siginfo_t>, uc: Option<&mut HandlerContext>) {
+	use crate::preemption::arm_deadline;
+	use crate::tunables::TimerMode;
+	use crate::tunables::timer_mode;
 	use crate::unfurl::Unfurl;
+	use crate::unwind::redirect;
 
+	use libc::SI_TIMER;
 	use timetravel::Swap;
 
 	let erryes = *errno();
@@ -428,7 +1360,26 @@ extern fn preempt(no: Signal, _: Option<&siginfo_t>, uc: Option<&mut HandlerCont
 	let relevant = thread_signal().map(|signal| no == signal).unwrap_or(false);
 	if relevant && is_preemptible() {
 		let deadline = DEADLINE.with(|deadline| deadline.get());
-		if nsnow() >= deadline {
+		let clock = CLOCK.with(|clock| clock.get());
+		let now = clock.now();
+		DEFERRAL.with(|deferral| {
+			// Is this the redelivery of a preemption we had to defer?
+			let (since, deferred) = deferral.get();
+			if since != 0 {
+				deferral.replace((0, deferred + now.saturating_sub(since)));
+			}
+		});
+		// Only a timer signal can have interrupted the function in its own code.  One that we
+		// redelivered arrived on the way back out of a nonpreemptible call instead.
+		let timer = info.map(|info| info.si_code == SI_TIMER).unwrap_or(false);
+		let unwinding = CANCELING.with(|canceling| canceling.get()).filter(|_| timer).map(|raise|
+			redirect(uc, raise, GUARD.with(|guard| guard.get()).1)
+		).unwrap_or(false);
+		if unwinding {
+			// We'll return straight into the unwinder, so make sure we only start it once.
+			CANCELING.with(|canceling| canceling.take());
+			*errno() = erryes;
+		} else if now >= deadline {
 			TASK.with(|task| {
 				// It's time to pause the function.  We need to save its state.
 				let mut task = task.borrow_mut();
@@ -451,6 +1402,12 @@ extern fn preempt(no: Signal, _: Option<&siginfo_t>, uc: Option<&mut HandlerCont
 				disable_preemption(None);
 			});
 		} else {
+			// A one-shot timer may have fired early (e.g., because it was armed for an
+			// older deadline), so make sure it will fire again when we actually want it.
+			if timer_mode() == TimerMode::Oneshot {
+				drop(arm_deadline(clock.into(), deadline));
+			}
+
 			*errno() = erryes;
 		}
 	} else {
@@ -458,6 +1415,15 @@ extern fn preempt(no: Signal, _: Option<&siginfo_t>, uc: Option<&mut HandlerCont
 			// The timed function has called into a nonpreemptible library function.
 			// We'll need to intercept it immediately upon the function's return.
 			defer_preemption((&mut uc.uc_sigmask, no).into());
+
+			// Only account for the delay if it's actually postponing a preemption.
+			let now = CLOCK.with(|clock| clock.get()).now();
+			if now >= DEADLINE.with(|deadline| deadline.get()) {
+				DEFERRAL.with(|deferral| {
+					let (_, deferred) = deferral.get();
+					deferral.replace((now, deferred));
+				});
+			}
 		} else {
 			// We still want to block this signal so it doesn't disturb us again.
 			uc.uc_sigmask.add(no);
@@ -467,13 +1433,85 @@ extern fn preempt(no: Signal, _: Option<&siginfo_t>, uc: Option<&mut HandlerCont
 	}
 }
 
-/// Bump the deadline forward by the current wall-clock time, unless the timeout is unlimited.
+/// Bump the deadline forward by the current time on the budget clock, unless it is already
+/// absolute.  In one-shot mode, also arm the timer to expire at the resulting deadline.
 fn stamp() {
-	DEADLINE.with(|deadline|
-		deadline.replace(deadline.get().checked_mul(1_000).map(|timeout|
-			nsnow() + timeout
-		).unwrap_or(deadline.get()))
-	);
+	use crate::preemption::arm_deadline;
+	use crate::tunables::TimerMode;
+	use crate::tunables::timer_mode;
+
+	let clock = CLOCK.with(|clock| clock.get());
+	let now = clock.now();
+	let mut contexts = RealThreadId::current().contexts().borrow_mut();
+	let mut frames = contexts.iter_mut().rev();
+	let current = frames.next();
+	let outer = frames.next();
+	let deadline = DEADLINE.with(|deadline| {
+		if let Some(timeout) = TIMEOUT.with(|timeout| timeout.take()) {
+			// A deadline too far in the future to represent is no deadline at all.
+			deadline.replace(now.checked_add(timeout).unwrap_or(u64::max_value()));
+		}
+		if let Some(outer) = outer.filter(|outer| outer.deadline != u64::max_value()) {
+			// Don't let a nested function run past the deadline of its enclosing one.
+			let remaining = outer.deadline.saturating_sub(outer.clock.now());
+			deadline.replace(deadline.get().min(now.saturating_add(remaining)));
+		}
+		deadline.get()
+	});
+	if let Some(current) = current {
+		current.deadline = deadline;
+	}
+	drop(contexts);
+	STAMP.with(|stamp| stamp.replace((now, deadline)));
+	if timer_mode() == TimerMode::Oneshot && deadline != u64::max_value() {
+		if let Err(or) = arm_deadline(clock.into(), deadline) {
+			abort(&format!("stamp(): failure in timer_settime(): {}", or));
+		}
+	}
+}
+
+/// Handler for segmentation faults.  If the running timed function has overflowed its stack,
+/// abandon it and return control to whoever launched or resumed it.
+extern fn overflow(no: Signal, info: Option<&siginfo_t>, uc: Option<&mut HandlerContext>) {
+	use crate::signals::forward_fault;
+	use crate::unfurl::Unfurl;
+
+	use timetravel::Swap;
+
+	// During thread teardown, the fault can't be on any timed function's stack.
+	let (lo, hi) = GUARD.try_with(|guard| guard.get()).unwrap_or_default();
+	let overflowed = info.map(|info| unsafe {
+		info.si_addr()
+	} as usize).map(|addr| lo <= addr && addr < hi).unwrap_or(false);
+	if overflowed {
+		let erryes = *errno();
+		let uc = unsafe {
+			uc.unfurl()
+		};
+
+		TASK.with(|task| {
+			let mut task = task.borrow_mut();
+			task.overflowed = true;
+
+			// Return into the checkpoint for its call site, never to resume the function.
+			let checkpoint = task.checkpoint.as_mut();
+			let checkpoint = unsafe {
+				checkpoint.unfurl()
+			};
+			checkpoint.swap(uc);
+
+			// Mark the function as paused so its library group gets reinitialized.
+			task.errno.replace(erryes);
+		});
+
+		// Block the preemption signal and disable preemption, just as preempt() would.
+		if let Ok(signal) = thread_signal() {
+			uc.uc_sigmask.add(signal);
+		}
+		disable_preemption(None);
+	} else {
+		forward_fault(no, info, uc);
+	}
 }
 
 /// Immediately yield the calling preemptible function.
@@ -487,16 +1525,10 @@ pub fn pause() {
 	defer_preemption(None);
 }
 
-/// Read the current wall-clock time, in nanoseconds.
+/// Read the current monotonic time, in nanoseconds.
 #[doc(hidden)]
 pub fn nsnow() -> u64 {
-	use std::time::UNIX_EPOCH;
-	use std::time::SystemTime;
-
-	let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("libinger: wall clock error");
-	let mut sum = now.subsec_nanos().into();
-	sum += now.as_secs() * 1_000_000_000;
-	sum
+	BudgetClock::Monotonic.now()
 }
 
 #[doc(hidden)]
@@ -12,6 +12,7 @@ fn main() {
 		\n\
 		typedef struct {{\n\
 			bool is_complete;\n\
+			bool is_poisoned;\n\
 			uint8_t continuation[{}];\n\
 		}} linger_t;\n\
 		\n\
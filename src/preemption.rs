@@ -1,4 +1,7 @@
-use crate::reusable::ReusableSync;
+use crate::linger::Frame;
+use crate::signals::SharedSignal;
+use crate::stacks::AltStack;
+use crate::timer::Clock;
 use crate::timer::Timer;
 
 use gotcha::Group;
@@ -10,14 +13,17 @@ use signal::Operation;
 use signal::Set;
 use signal::Signal;
 use signal::Sigset;
-use signal::sigaction;
 use std::cell::RefCell;
+use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Result as IoResult;
 use std::os::raw::c_int;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
 thread_local! {
+	// The kernel thread running the timed function whose TCB this is.  Only bound while it runs,
+	// since it may be resumed on another thread and this one may then exit.
 	static SIGNAL: RefCell<Option<RealThreadId>> = RefCell::default();
 
 	// Whether we had to delay preemption checks until the end of a nonpreemptible call.
@@ -30,8 +36,8 @@ pub fn thread_signal() -> Result<Signal, ()> {
 	// that the current thread has no preemption signal assigned (any longer).
 	SIGNAL.try_with(|signal|
 		signal.borrow().as_ref().map(|signal| {
-			let RealThreadId (signal) = signal;
-			signal.borrow().as_ref().map(|signal|
+			let RealThreadId (thread) = signal;
+			thread.signaler.borrow().as_ref().map(|signal|
 				*signal.signal
 			)
 		}).unwrap_or(None).ok_or(())
@@ -89,7 +95,12 @@ pub fn disable_preemption(block: Option<Signal>) {
 		drop(mask(Operation::Block, signal));
 	}
 
-	SIGNAL.with(|signal| signal.replace(None));
+	if let Some(RealThreadId (thread)) = SIGNAL.with(|signal| signal.replace(None)) {
+		// Don't leave a one-shot timer pending now that nothing is running.
+		if let Some(signal) = thread.signaler.borrow_mut().as_mut() {
+			drop(signal.disarm());
+		}
+	}
 	DEFERRED.with(|deferred| deferred.store(false, Ordering::Relaxed));
 }
 
@@ -115,14 +126,65 @@ pub fn defer_preemption(signum: Option<(&mut Sigset, Signal)>) {
 	DEFERRED.with(|deferred| deferred.store(true, Ordering::Relaxed));
 }
 
-pub fn thread_setup(thread: RealThreadId, handler: Handler, quantum: u64) -> IoResult<()> {
+// It is only safe to call this function while preemption is (temporarily) disabled!
+pub fn arm_deadline(clock: Clock, deadline: u64) -> IoResult<()> {
+	SIGNAL.with(|signal| {
+		if let Some(RealThreadId (thread)) = signal.borrow().as_ref() {
+			if let Some(signal) = thread.signaler.borrow_mut().as_mut() {
+				signal.oneshot(clock, deadline)?;
+			}
+		}
+		Ok(())
+	})
+}
+
+/// Return control to a timed function that called a nested one, whose preemption the latter has
+/// left disabled.  If the enclosing function's `deadline` on `clock` has `expired`, it is
+/// preempted as soon as it returns to preemptible code.  Call with its TCB installed!
+pub fn resume_outer(clock: Clock, deadline: u64, expired: bool) -> IoResult<()> {
+	use crate::tunables::TimerMode;
+	use crate::tunables::quantum;
+	use crate::tunables::timer_mode;
+
+	if expired {
+		defer_preemption(None);
+		Ok(())
+	} else {
+		if timer_mode() == TimerMode::Oneshot && deadline != u64::max_value() {
+			arm_deadline(clock, deadline)?;
+		} else if timer_mode() == TimerMode::Periodic {
+			// The nested function may have moved the timer onto its own budget clock.
+			SIGNAL.with(|signal| -> IoResult<()> {
+				if let Some(RealThreadId (thread)) = signal.borrow().as_ref() {
+					if let Some(signal) = thread.signaler.borrow_mut().as_mut() {
+						signal.rearm(clock, quantum())?;
+					}
+				}
+				Ok(())
+			})?;
+		}
+		mask(Operation::Unblock, thread_signal().map_err(|_| Error::from(ErrorKind::NotFound))?)
+	}
+}
+
+/// Configure the current kernel thread's preemption timer to fire every `quantum` microseconds
+/// of time on `clock`, or (if `0`) to wait to be armed for a specific deadline.
+pub fn thread_setup(thread: RealThreadId, handler: Handler, clock: Clock, quantum: u64)
+-> IoResult<()> {
 	use gotcha::shared_hook;
 	use std::sync::Once;
 
-	let RealThreadId (signal) = thread;
-	if signal.borrow().is_none() {
-		signal.replace(Some(PreemptionSignal::new(handler, quantum)?));
+	let RealThreadId (real) = thread;
+	let mut signaler = real.signaler.borrow_mut();
+	if let Some(signaler) = signaler.as_mut() {
+		// Reprogram the existing timer in case the clock or quantum has since been changed.
+		signaler.rearm(clock, quantum)?;
+	} else {
+		signaler.replace(PreemptionSignal::new(handler, clock, quantum)?);
 	}
+	drop(signaler);
+
+	// The function may have last run on another kernel thread, so always rebind it to this one.
 	SIGNAL.with(|signal| signal.replace(Some(thread)));
 
 	static INIT: Once = Once::new();
@@ -131,6 +193,12 @@ pub fn thread_setup(thread: RealThreadId, handler: Handler, quantum: u64) -> IoR
 	Ok(())
 }
 
+/// Unbind the installed TCB from the current kernel thread, which the timed function may never run
+/// on again.  Call before uninstalling it!
+pub fn thread_unbind() {
+	SIGNAL.with(|signal| signal.take());
+}
+
 fn errno_group(group: Option<Group>) -> &'static mut c_int {
 	use gotcha::group_lookup_symbol_fn;
 	use libc::__errno_location;
@@ -163,66 +231,158 @@ fn mask(un: Operation, no: Signal) -> IoResult<()> {
 	pthread_sigmask(un, &set, None)
 }
 
-pub struct RealThreadId (&'static RefCell<Option<PreemptionSignal>>);
+#[derive(Clone, Copy)]
+pub struct RealThreadId (&'static RealThread);
+
+/// State belonging to a kernel thread rather than to any timed function it happens to be running.
+#[derive(Default)]
+struct RealThread {
+	signaler: RefCell<Option<PreemptionSignal>>,
+	// The timed functions currently running on this thread, outermost first.
+	contexts: RefCell<Vec<Frame>>,
+}
 
 impl RealThreadId {
 	pub fn current() -> Self {
 		use crate::lifetime::unbound;
 
 		thread_local! {
-			static SIGNALER: RefCell<Option<PreemptionSignal>> = RefCell::default();
+			static THREAD: RealThread = RealThread::default();
+		}
+
+		// From within a timed function's TCB, find the kernel thread that's running it.
+		if let Some(thread) = SIGNAL.with(|signal| *signal.borrow()) {
+			return thread;
 		}
 
-		Self (SIGNALER.with(|signaler| unsafe {
-			unbound(signaler)
+		Self (THREAD.with(|thread| unsafe {
+			unbound(thread)
 		}))
 	}
+
+	/// The stack of timed functions running on this kernel thread, each nested inside the last.
+	pub fn contexts(self) -> &'static RefCell<Vec<Frame>> {
+		let Self (thread) = self;
+		&thread.contexts
+	}
 }
 
 struct PreemptionSignal {
-	signal: ReusableSync<'static, Signal>,
+	signal: SharedSignal,
 	timer: Timer,
+	clock: Clock,
+	quantum: u64,
+	// Not related to preemption, but also needed once per kernel thread.
+	_altstack: AltStack,
 }
 
 impl PreemptionSignal {
-	fn new(handler: Handler, quantum: u64) -> IoResult<Self> {
+	fn new(handler: Handler, clock: Clock, quantum: u64) -> IoResult<Self> {
 		use crate::signals::assign_signal;
-		use crate::timer::Clock;
-		use crate::timer::Sigevent;
+
+		let _altstack = AltStack::new()?;
+		let signal = assign_signal(handler)?;
+		mask(Operation::Block, *signal)?;
+
+		let timer = thread_timer(clock, *signal)?;
+		let mut this = Self {
+			signal,
+			timer,
+			clock,
+			quantum: 0,
+			_altstack,
+		};
+		this.rearm(clock, quantum)?;
+		Ok(this)
+	}
+
+	/// Program the timer to fire every `quantum` microseconds of time on `clock`, unless it is
+	/// already doing so.  A `quantum` of `0` disarms the timer.
+	fn rearm(&mut self, clock: Clock, quantum: u64) -> IoResult<()> {
 		use crate::timer::itimerspec;
-		use crate::timer::timer_create;
 		use crate::timer::timer_settime;
 
-		use libc::SA_RESTART;
-		use libc::SA_SIGINFO;
-		use libc::timespec;
-		use signal::Action;
-		use signal::Sigaction;
+		if quantum != 0 {
+			// Follow the timed function's budget clock, so that, e.g., one measured in CPU
+			// time isn't interrupted while it's blocked.
+			self.reclock(clock)?;
+		}
+		if quantum != self.quantum {
+			let period = timespec(quantum.saturating_mul(1_000));
+			let it = itimerspec {
+				it_interval: period,
+				it_value: period,
+			};
+			timer_settime(self.timer, false, &it, None)?;
+			self.quantum = quantum;
+		}
+
+		Ok(())
+	}
 
-		let signal = assign_signal().expect("libinger: no available signal for preempting this thread");
-		let sa = Sigaction::new(handler, Sigset::empty(), SA_SIGINFO | SA_RESTART);
-		sigaction(*signal, &sa, None)?;
-		mask(Operation::Block, *signal)?;
+	/// Program the timer to fire once when `clock` reaches `deadline`, in nanoseconds.
+	fn oneshot(&mut self, clock: Clock, deadline: u64) -> IoResult<()> {
+		use crate::timer::itimerspec;
+		use crate::timer::timer_settime;
+
+		self.reclock(clock)?;
 
-		let mut se = Sigevent::signal(*signal);
-		let timer = timer_create(Clock::Real, &mut se)?;
-		let quantum: i64 = quantum as _;
-		let mut it = itimerspec {
-			it_interval: timespec {
-				tv_sec: 0,
-				tv_nsec: quantum * 1_000,
-			},
-			it_value: timespec {
-				tv_sec: 0,
-				tv_nsec: quantum * 1_000,
-			},
+		let it = itimerspec {
+			it_interval: timespec(0),
+			it_value: timespec(deadline),
 		};
-		timer_settime(timer, false, &mut it, None)?;
+		timer_settime(self.timer, true, &it, None)?;
+		self.quantum = 0;
 
-		Ok(Self {
-			signal,
-			timer,
-		})
+		Ok(())
+	}
+
+	/// Cancel any pending one-shot expiration, but leave a periodic timer running.
+	fn disarm(&mut self) -> IoResult<()> {
+		use crate::timer::itimerspec;
+		use crate::timer::timer_settime;
+
+		if self.quantum == 0 {
+			let it = itimerspec {
+				it_interval: timespec(0),
+				it_value: timespec(0),
+			};
+			timer_settime(self.timer, false, &it, None)?;
+		}
+
+		Ok(())
+	}
+
+	/// Replace the timer with a disarmed one measuring `clock`, unless it already does.
+	fn reclock(&mut self, clock: Clock) -> IoResult<()> {
+		use crate::timer::timer_delete;
+
+		use std::mem::replace;
+
+		if clock != self.clock {
+			let timer = thread_timer(clock, *self.signal)?;
+			timer_delete(replace(&mut self.timer, timer))?;
+			self.clock = clock;
+			self.quantum = 0;
+		}
+
+		Ok(())
+	}
+}
+
+/// Create a timer on `clock` whose notifications go only to the calling kernel thread.
+fn thread_timer(clock: Clock, signal: Signal) -> IoResult<Timer> {
+	use crate::timer::Sigevent;
+	use crate::timer::timer_create;
+
+	let mut se = Sigevent::thread_id(signal, gettid());
+	timer_create(clock, &mut se)
+}
+
+fn timespec(ns: u64) -> libc::timespec {
+	libc::timespec {
+		tv_sec: (ns / 1_000_000_000) as _,
+		tv_nsec: (ns % 1_000_000_000) as _,
 	}
 }
 
@@ -233,8 +393,15 @@ impl Drop for PreemptionSignal {
 		if let Err(or) = timer_delete(self.timer) {
 			eprintln!("libinger: unable to delete POSIX timer: {}", or);
 		}
-		if let Err(or) = sigaction(*self.signal, &(), None) {
-			eprintln!("libinger: unable to unregister signal handler: {}", or);
-		}
 	}
 }
+
+fn gettid() -> c_int {
+	use libc::SYS_gettid;
+	use libc::syscall;
+
+	let tid = unsafe {
+		syscall(SYS_gettid)
+	};
+	tid as _
+}
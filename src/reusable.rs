@@ -1,39 +1,57 @@
 use std::cell::BorrowMutError;
 use std::cell::RefCell;
 use std::cell::RefMut;
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::result::Result as StdResult;
+use std::sync::Condvar;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
 use std::sync::PoisonError;
+use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::task::Waker;
+use std::time::Duration;
 
-type Sync<T> = Mutex<Vec<T>>;
+type Sync<T> = Waitable<Vec<T>>;
 type Unsync<T> = RefCell<Vec<T>>;
 
 pub struct Reusable<'a, T, A = Unsync<T>>
-where &'a A: SharedMut<Vec<T>> {
+where &'a A: Shared<T> {
 	value: Option<T>,
 	pool: &'a A,
+	recycler: Option<fn(&mut T)>,
+	max_idle: Option<fn() -> usize>,
 }
 
 pub type ReusableSync<'a, T> = Reusable<'a, T, Sync<T>>;
 
 impl<'a, T, A, B: Fn() -> Option<T>> TryFrom<&'a Pool<T, B, A>> for Reusable<'a, T, A>
-where &'a A: SharedMut<Vec<T>> {
-	type Error = Option<<&'a A as SharedMut<Vec<T>>>::Error>;
+where &'a A: Shared<T> {
+	type Error = Option<<&'a A as Shared<T>>::Error>;
 
 	fn try_from(pool: &'a Pool<T, B, A>) -> StdResult<Reusable<'a, T, A>, Self::Error> {
 		let builder = &pool.builder;
+		let recycler = pool.recycler;
+		let max_idle = pool.max_idle;
 		let pool = &pool.allocated;
-		let value = pool.try_into_inner()?.pop().or_else(builder);
+		let value = pool.pop()?.or_else(builder);
 		if value.is_some() {
 			Ok(Self {
 				value,
 				pool,
+				recycler,
+				max_idle,
 			})
 		} else {
 			Err(None)
@@ -42,7 +60,7 @@ where &'a A: SharedMut<Vec<T>> {
 }
 
 impl<'a, T, A> Deref for Reusable<'a, T, A>
-where &'a A: SharedMut<Vec<T>> {
+where &'a A: Shared<T> {
 	type Target = T;
 
 	fn deref(&self) -> &Self::Target {
@@ -52,7 +70,7 @@ where &'a A: SharedMut<Vec<T>> {
 }
 
 impl<'a, T, A> DerefMut for Reusable<'a, T, A>
-where &'a A: SharedMut<Vec<T>> {
+where &'a A: Shared<T> {
 	fn deref_mut(&mut self) -> &mut Self::Target {
 		// Can only be None if we're called while being dropped!
 		self.value.as_mut().unwrap()
@@ -60,51 +78,233 @@ where &'a A: SharedMut<Vec<T>> {
 }
 
 impl<'a, T, A> Drop for Reusable<'a, T, A>
-where &'a A: SharedMut<Vec<T>> {
+where &'a A: Shared<T> {
 	fn drop(&mut self) {
 		// Can only be None on a double drop!
-		let value = self.value.take().unwrap();
+		let mut value = self.value.take().unwrap();
+		if let Some(recycler) = self.recycler {
+			recycler(&mut value);
+		}
 
-		// Panic instead of losing this value.
-		self.pool.try_into_inner().unwrap().push(value)
+		// Panic instead of losing this value, which only an unsynchronized pool that's already
+		// borrowed can make us do.  If the pool was full, this destroys it instead.
+		let max_idle = self.max_idle.map(|max_idle| max_idle()).unwrap_or(usize::max_value());
+		self.pool.push(value, max_idle).unwrap();
 	}
 }
 
 pub struct Pool<T, B: ?Sized = fn() -> Option<T>, A = Unsync<T>> {
 	_type: PhantomData<T>,
 	allocated: A,
+	recycler: Option<fn(&mut T)>,
+	max_idle: Option<fn() -> usize>,
 	builder: B,
 }
 
 pub type SyncPool<T, B = fn() -> Option<T>> = Pool<T, B, Sync<T>>;
 
+pub type LockFreePool<T, B = fn() -> Option<T>> = Pool<T, B, LockFree<T>>;
+
+impl<'a, T, F: Fn() -> Option<T>> SyncPool<T, F> {
+	/// Number of callers currently queued waiting for a value.
+	pub fn waiters(&self) -> usize {
+		self.allocated.lock().waiters.len()
+	}
+
+	/// Get a value, but only if `admit` approves given the number of values currently sitting
+	/// in the pool.  Both happen atomically with respect to other users of the pool.  Fails
+	/// without taking one if anyone already queued waiting would be admitted, so as not to cut in
+	/// line.  Of the values in the pool, the most recently returned one that the caller would
+	/// `prefer` wins.
+	pub fn try_take_if(&'a self, admit: fn(usize) -> bool, prefer: impl Fn(&T) -> bool)
+	-> SyncResult<'a, T> {
+		let mut state = self.allocated.lock();
+		let pooled = state.values.len();
+		if ! admit(pooled) || state.waiters.iter().any(|&(_, admit)| admit(pooled)) {
+			return Err(None);
+		}
+
+		let values = &mut state.values;
+		let value = values.iter().rposition(prefer).map(|index| values.swap_remove(index))
+			.or_else(|| values.pop()).or_else(&self.builder).ok_or(None)?;
+		Ok(Reusable {
+			value: Some(value),
+			pool: &self.allocated,
+			recycler: self.recycler,
+			max_idle: self.max_idle,
+		})
+	}
+
+	/// Call `fun` on each value sitting in the pool, atomically with respect to other users.
+	pub fn inspect(&self, fun: impl FnMut(&mut T)) {
+		self.allocated.lock().values.iter_mut().for_each(fun);
+	}
+
+	/// Join the back of the queue of callers waiting for a value that `admit` approves (see
+	/// `try_take_if()`).
+	pub fn enqueue(&'a self, admit: fn(usize) -> bool) -> Ticket<'a, T, F> {
+		let mut state = self.allocated.lock();
+		let ticket = state.next;
+		state.next += 1;
+		state.waiters.push_back((ticket, admit));
+		Ticket {
+			pool: self,
+			ticket,
+			admit,
+		}
+	}
+
+	/// Get a value that `admit` approves (see `try_take_if()`), waiting up to `timeout` (or
+	/// indefinitely if `None`) for one to be returned if there is none.  Waiters are served in
+	/// the order they arrived, except that one is passed over while `admit` turns it away.
+	pub fn wait(&'a self, timeout: Option<Duration>, admit: fn(usize) -> bool)
+	-> SyncResult<'a, T> {
+		use std::time::Instant;
+
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		let mut ticket = self.enqueue(admit);
+		let returned = &self.allocated.returned;
+		let mut locked = self.allocated.lock();
+		loop {
+			if let Some((value, wakers)) = ticket.take(&mut locked) {
+				drop(locked);
+				wake(wakers);
+				return Ok(value);
+			}
+
+			locked = if let Some(deadline) = deadline {
+				let now = Instant::now();
+				if now >= deadline {
+					// Release the lock so the ticket can remove itself from the queue.
+					drop(locked);
+					return Err(None);
+				}
+				returned.wait_timeout(locked, deadline - now).map(|(locked, _)| locked)
+					.unwrap_or_else(|poison| poison.into_inner().0)
+			} else {
+				returned.wait(locked).unwrap_or_else(|poison| poison.into_inner())
+			};
+		}
+	}
+}
+
+/// A place in the queue of callers waiting for a value from a `SyncPool`.  Dropping it gives up
+/// that place.
+pub struct Ticket<'a, T, F: Fn() -> Option<T>> {
+	pool: &'a SyncPool<T, F>,
+	ticket: u64,
+	admit: fn(usize) -> bool,
+}
+
+impl<'a, T, F: Fn() -> Option<T>> Ticket<'a, T, F> {
+	/// Get a value if it is our turn and one is available.  Otherwise, arrange for `waker` (if
+	/// any) to be woken the next time a value is returned to the pool.
+	pub fn poll(&mut self, waker: Option<&Waker>) -> Option<SyncResult<'a, T>> {
+		let mut state = self.pool.allocated.lock();
+		if let Some((value, wakers)) = self.take(&mut state) {
+			drop(state);
+			wake(wakers);
+			Some(Ok(value))
+		} else {
+			if let Some(waker) = waker {
+				state.wakers.push(waker.clone());
+			}
+			None
+		}
+	}
+
+	/// Get a value if it is our turn and one is available, along with the wakers of the other
+	/// waiters, which the caller must wake once it has released the lock.
+	fn take(&mut self, state: &mut Queued<Vec<T>>)
+	-> Option<(Reusable<'a, T, Sync<T>>, Vec<Waker>)> {
+		use std::mem::take;
+
+		// We may skip ahead of waiters who wouldn't be admitted anyway, but no one else.
+		let pooled = state.values.len();
+		let ticket = self.ticket;
+		let place = state.waiters.iter().position(|&(waiter, admit)|
+			waiter == ticket || admit(pooled)
+		)?;
+		if state.waiters[place].0 != ticket || ! (self.admit)(pooled) {
+			return None;
+		}
+
+		let value = state.values.pop().or_else(&self.pool.builder)?;
+		state.waiters.remove(place);
+		self.ticket = u64::max_value();
+
+		// Whoever is next in line may also be able to get a value.
+		self.pool.allocated.returned.notify_all();
+
+		Some((Reusable {
+			value: Some(value),
+			pool: &self.pool.allocated,
+			recycler: self.pool.recycler,
+			max_idle: self.pool.max_idle,
+		}, take(&mut state.wakers)))
+	}
+}
+
+impl<T, F: Fn() -> Option<T>> Drop for Ticket<'_, T, F> {
+	fn drop(&mut self) {
+		use std::mem::take;
+
+		if self.ticket != u64::max_value() {
+			let mut state = self.pool.allocated.lock();
+			state.waiters.retain(|&(ticket, _)| ticket != self.ticket);
+			let wakers = take(&mut state.wakers);
+			drop(state);
+			wake(wakers);
+			self.pool.allocated.returned.notify_all();
+		}
+	}
+}
+
+/// Wake each of `wakers`.  Never call this with a pool's lock held, since a waker may run its task
+/// on the spot and try to take the lock itself.
+fn wake(wakers: Vec<Waker>) {
+	for waker in wakers {
+		waker.wake();
+	}
+}
+
 impl<'a, T, F: Fn() -> Option<T>, C: Default + 'a> Pool<T, F, C>
-where &'a C: SharedMut<Vec<T>> {
+where &'a C: Shared<T> {
 	pub fn new(builder: F) -> Self {
 		Self {
 			_type: PhantomData::default(),
 			allocated: C::default(),
+			recycler: None,
+			max_idle: None,
 			builder: builder,
 		}
 	}
 
-	pub fn prealloc(&'a self, count: usize)
-	-> StdResult<(), Option<<&'a C as SharedMut<Vec<T>>>::Error>> {
-		use std::collections::LinkedList;
-		use std::convert::TryInto;
+	/// Call `recycler` on each value as it is returned to the pool.
+	pub fn with_recycler(mut self, recycler: fn(&mut T)) -> Self {
+		self.recycler.replace(recycler);
+		self
+	}
 
-		let swap: LinkedList<StdResult<Reusable<_, _>, _>> = (0..count).map(|_|
-			self.try_into()
-		).collect();
-		for temp in swap {
-			temp?;
-		}
-		Ok(())
+	/// Destroy, rather than keep, values returned while the pool already holds `max_idle()`.
+	pub fn with_max_idle(mut self, max_idle: fn() -> usize) -> Self {
+		self.max_idle.replace(max_idle);
+		self
+	}
+
+	/// Number of values currently sitting in the pool.
+	pub fn pooled(&'a self) -> usize {
+		self.allocated.len()
+	}
+
+	/// Destroy idle values until at most `keep` remain, returning how many were destroyed.
+	pub fn trim(&'a self, keep: usize) -> StdResult<usize, <&'a C as Shared<T>>::Error> {
+		Ok(self.allocated.trim(keep)?.len())
 	}
 }
 
 impl<'a, T: Default, C: Default + 'a> Default for Pool<T, fn() -> Option<T>, C>
-where &'a C: SharedMut<Vec<T>> {
+where &'a C: Shared<T> {
 	fn default() -> Self {
 		Self::new(|| Some(T::default()))
 	}
@@ -112,17 +312,86 @@ where &'a C: SharedMut<Vec<T>> {
 
 pub type Result<'a, T, A = Unsync<T>> = StdResult<
 	Reusable<'a, T, A>,
-	Option<<&'a A as SharedMut<Vec<T>>>::Error>,
+	Option<<&'a A as Shared<T>>::Error>,
 >;
 
 pub type SyncResult<'a, T> = Result<'a, T, Sync<T>>;
 
+pub type ReusableLockFree<'a, T> = Reusable<'a, T, LockFree<T>>;
+
+/// What became of a value returned to a pool.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pushed {
+	/// It's waiting in the pool for its next user.
+	Kept,
+	/// The pool was full, so it was destroyed.
+	Destroyed,
+}
+
+/// Operations on a shared container of pooled values.
+#[doc(hidden)]
+pub trait Shared<T>: Copy {
+	type Error: Debug;
+
+	fn pop(self) -> StdResult<Option<T>, Self::Error>;
+
+	/// Add `value` unless the container already holds `max` values, in which case destroy it.
+	fn push(self, value: T, max: usize) -> StdResult<Pushed, Self::Error>;
+
+	fn len(self) -> usize;
+
+	/// Remove values until at most `keep` remain, returning the excess.
+	fn trim(self, keep: usize) -> StdResult<Vec<T>, Self::Error>;
+}
+
+impl<T, S: SharedMut<Vec<T>> + Copy> Shared<T> for S {
+	type Error = S::Error;
+
+	fn pop(self) -> StdResult<Option<T>, Self::Error> {
+		Ok(self.try_into_inner()?.pop())
+	}
+
+	fn push(self, value: T, max: usize) -> StdResult<Pushed, Self::Error> {
+		let mut values = self.try_into_inner()?;
+		if values.len() < max {
+			values.push(value);
+			S::changed(values);
+			Ok(Pushed::Kept)
+		} else {
+			// Destroy the value after releasing the lock.
+			drop(values);
+			drop(value);
+			Ok(Pushed::Destroyed)
+		}
+	}
+
+	fn len(self) -> usize {
+		self.try_into_inner().map(|values| values.len()).unwrap_or_default()
+	}
+
+	fn trim(self, keep: usize) -> StdResult<Vec<T>, Self::Error> {
+		let mut values = self.try_into_inner()?;
+		Ok(if values.len() > keep {
+			values.drain(keep..).collect()
+		} else {
+			Vec::new()
+		})
+	}
+}
+
 #[doc(hidden)]
 pub trait SharedMut<T> {
 	type Okay: DerefMut<Target = T>;
 	type Error: Debug;
 
 	fn try_into_inner(self) -> StdResult<Self::Okay, Self::Error>;
+
+	/// Release access obtained from `try_into_inner()` after changing the contents, letting
+	/// anyone waiting on the container know.
+	fn changed(okay: Self::Okay) {
+		drop(okay);
+	}
 }
 
 impl<'a, T> SharedMut<T> for &'a RefCell<T> {
@@ -142,3 +411,312 @@ impl<'a, T> SharedMut<T> for &'a Mutex<T> {
 		self.lock()
 	}
 }
+
+/// A mutex that callers can wait on for its contents to change, in first-come, first-served order.
+/// Since no one ever leaves its contents half-updated, it shrugs off poisoning.
+#[derive(Default)]
+pub struct Waitable<T> {
+	state: Mutex<Queued<T>>,
+	returned: Condvar,
+}
+
+impl<T> Waitable<T> {
+	fn lock(&self) -> MutexGuard<'_, Queued<T>> {
+		self.state.lock().unwrap_or_else(|poison| poison.into_inner())
+	}
+}
+
+#[doc(hidden)]
+#[derive(Default)]
+pub struct Queued<T> {
+	values: T,
+	// Tickets of the callers waiting in line, each with the admission check it applies.
+	waiters: VecDeque<(u64, fn(usize) -> bool)>,
+	next: u64,
+	wakers: Vec<Waker>,
+}
+
+#[doc(hidden)]
+pub struct QueuedGuard<'a, T> (MutexGuard<'a, Queued<T>>, &'a Waitable<T>);
+
+impl<T> Deref for QueuedGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &Self::Target {
+		let Self (this, _) = self;
+		&this.values
+	}
+}
+
+impl<T> DerefMut for QueuedGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		let Self (this, _) = self;
+		&mut this.values
+	}
+}
+
+impl<'a, T> SharedMut<T> for &'a Waitable<T> {
+	type Okay = QueuedGuard<'a, T>;
+	type Error = PoisonError<MutexGuard<'a, Queued<T>>>;
+
+	fn try_into_inner(self) -> StdResult<Self::Okay, Self::Error> {
+		Ok(QueuedGuard (self.lock(), self))
+	}
+
+	fn changed(okay: Self::Okay) {
+		use std::mem::take;
+
+		let QueuedGuard (mut state, this) = okay;
+		let wakers = take(&mut state.wakers);
+		drop(state);
+		wake(wakers);
+		this.returned.notify_all();
+	}
+}
+
+/// A lock-free stack of values (i.e., a Treiber stack), which can't be poisoned.
+///
+/// Rather than being freed, its nodes are recycled via a second such stack, so it's always safe
+/// to follow a stale reference to one.  Nodes live in an arena and are referred to by index, which
+/// leaves half of each head word free for a generation count that prevents the ABA problem.
+pub struct LockFree<T> {
+	values: Links,
+	spares: Links,
+	nodes: Arena<T>,
+	len: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for LockFree<T> {}
+unsafe impl<T: Send> std::marker::Sync for LockFree<T> {}
+
+impl<T> Default for LockFree<T> {
+	fn default() -> Self {
+		Self {
+			values: Links::default(),
+			spares: Links::default(),
+			nodes: Arena::default(),
+			len: AtomicUsize::default(),
+		}
+	}
+}
+
+impl<T> Drop for LockFree<T> {
+	fn drop(&mut self) {
+		// The arena frees the nodes themselves, but not the values they hold.
+		while let Ok(Some(value)) = Shared::pop(&*self) {
+			drop(value);
+		}
+	}
+}
+
+impl<'a, T> Shared<T> for &'a LockFree<T> {
+	type Error = Infallible;
+
+	fn pop(self) -> StdResult<Option<T>, Self::Error> {
+		Ok(self.values.pop(&self.nodes).map(|index| {
+			let value = unsafe {
+				self.nodes[index].value.get().read().assume_init()
+			};
+			self.spares.push(&self.nodes, index);
+			self.len.fetch_sub(1, Ordering::Relaxed);
+			value
+		}))
+	}
+
+	fn push(self, value: T, max: usize) -> StdResult<Pushed, Self::Error> {
+		// Count the value before publishing it so the length never underflows.
+		if self.len.fetch_add(1, Ordering::Relaxed) >= max {
+			self.len.fetch_sub(1, Ordering::Relaxed);
+			drop(value);
+			return Ok(Pushed::Destroyed);
+		}
+
+		let index = self.spares.pop(&self.nodes).or_else(|| self.nodes.alloc());
+		let index = if let Some(index) = index {
+			index
+		} else {
+			// We've somehow used up every index, so there's nowhere to keep it.
+			self.len.fetch_sub(1, Ordering::Relaxed);
+			drop(value);
+			return Ok(Pushed::Destroyed);
+		};
+		unsafe {
+			self.nodes[index].value.get().write(MaybeUninit::new(value));
+		}
+		self.values.push(&self.nodes, index);
+		Ok(Pushed::Kept)
+	}
+
+	fn len(self) -> usize {
+		self.len.load(Ordering::Relaxed)
+	}
+
+	fn trim(self, keep: usize) -> StdResult<Vec<T>, Self::Error> {
+		let mut excess = Vec::new();
+		while self.len() > keep {
+			if let Some(value) = Shared::pop(self)? {
+				excess.push(value);
+			} else {
+				break;
+			}
+		}
+		Ok(excess)
+	}
+}
+
+struct Node<T> {
+	// Index of the next node down, plus one (so that zero means there isn't one).
+	next: AtomicU32,
+	value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Default for Node<T> {
+	fn default() -> Self {
+		Self {
+			next: AtomicU32::default(),
+			value: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+}
+
+/// Each head word holds a node's index (plus one, with zero meaning the stack is empty) in its low
+/// half and a generation count in its high half.
+#[derive(Default)]
+struct Links {
+	head: AtomicU64,
+}
+
+const INDEX_BITS: u32 = 32;
+const INDEX_MASK: u64 = (1 << INDEX_BITS) - 1;
+
+impl Links {
+	fn push<T>(&self, nodes: &Arena<T>, index: u32) {
+		let node = &nodes[index];
+		let mut head = self.head.load(Ordering::Relaxed);
+		loop {
+			node.next.store((head & INDEX_MASK) as _, Ordering::Relaxed);
+			let tagged = (u64::from(index) + 1) | next_tag(head);
+			match self.head.compare_exchange_weak(
+				head,
+				tagged,
+				Ordering::Release,
+				Ordering::Relaxed,
+			) {
+				Ok(_) => break,
+				Err(current) => head = current,
+			}
+		}
+	}
+
+	fn pop<T>(&self, nodes: &Arena<T>) -> Option<u32> {
+		let mut head = self.head.load(Ordering::Acquire);
+		loop {
+			let index = (head & INDEX_MASK).checked_sub(1)? as u32;
+
+			// The node might have been popped and reused since we read the head, but it can't
+			// have been freed; if it has changed, the tag will make our exchange fail.
+			let next = nodes[index].next.load(Ordering::Relaxed);
+			match self.head.compare_exchange_weak(
+				head,
+				u64::from(next) | next_tag(head),
+				Ordering::Acquire,
+				Ordering::Acquire,
+			) {
+				Ok(_) => return Some(index),
+				Err(current) => head = current,
+			}
+		}
+	}
+}
+
+fn next_tag(head: u64) -> u64 {
+	(head & ! INDEX_MASK).wrapping_add(1 << INDEX_BITS)
+}
+
+/// Number of segments in an arena.  Segment `n` holds `2^n` nodes, so together they can hold as
+/// many as there are 32-bit indices.
+const SEGMENTS: usize = INDEX_BITS as _;
+
+/// Append-only storage for the nodes of a `LockFree`, which never moves or frees one until the
+/// whole arena is dropped.
+struct Arena<T> {
+	segments: [AtomicPtr<Node<T>>; SEGMENTS],
+	allocated: AtomicU32,
+}
+
+impl<T> Default for Arena<T> {
+	fn default() -> Self {
+		Self {
+			segments: Default::default(),
+			allocated: AtomicU32::default(),
+		}
+	}
+}
+
+impl<T> Arena<T> {
+	/// Add a node, returning its index, or `None` if every index is taken.
+	fn alloc(&self) -> Option<u32> {
+		use std::ptr::null_mut;
+
+		let index = self.allocated.fetch_add(1, Ordering::Relaxed);
+		if index == u32::max_value() {
+			// Keep the count pinned so it can't wrap around to indices that are in use.
+			self.allocated.store(u32::max_value(), Ordering::Relaxed);
+			return None;
+		}
+
+		let (segment, _) = locate(index);
+		let nodes = &self.segments[segment];
+		if nodes.load(Ordering::Acquire).is_null() {
+			let new: Box<[Node<T>]> = (0..1usize << segment).map(|_| Node::default()).collect();
+			let new = Box::into_raw(new) as *mut Node<T>;
+			if nodes.compare_exchange(null_mut(), new, Ordering::AcqRel, Ordering::Acquire).is_err() {
+				// Someone else got there first.
+				drop(unsafe {
+					free(new, segment)
+				});
+			}
+		}
+		Some(index)
+	}
+}
+
+impl<T> std::ops::Index<u32> for Arena<T> {
+	type Output = Node<T>;
+
+	fn index(&self, index: u32) -> &Self::Output {
+		let (segment, offset) = locate(index);
+		let nodes = self.segments[segment].load(Ordering::Acquire);
+		debug_assert!(! nodes.is_null(), "Arena::index(): node was never allocated");
+		unsafe {
+			&*nodes.add(offset)
+		}
+	}
+}
+
+impl<T> Drop for Arena<T> {
+	fn drop(&mut self) {
+		for (segment, nodes) in self.segments.iter().enumerate() {
+			let nodes = nodes.load(Ordering::Relaxed);
+			if ! nodes.is_null() {
+				drop(unsafe {
+					free(nodes, segment)
+				});
+			}
+		}
+	}
+}
+
+/// Find which segment holds the node at `index`, and where within it.
+fn locate(index: u32) -> (usize, usize) {
+	let position = u64::from(index) + 1;
+	let segment = 63 - position.leading_zeros();
+	(segment as _, (position - (1 << segment)) as _)
+}
+
+/// Reclaim a segment of nodes allocated by `Arena::alloc()`.
+unsafe fn free<T>(nodes: *mut Node<T>, segment: usize) -> Box<[Node<T>]> {
+	use std::ptr::slice_from_raw_parts_mut;
+
+	Box::from_raw(slice_from_raw_parts_mut(nodes, 1 << segment))
+}
@@ -1,49 +1,148 @@
-use crate::reusable::SyncResult;
-
+use libc::ucontext_t;
+use signal::Handler;
+use signal::Sigaction;
 use signal::Signal;
+use signal::sigaction;
+use signal::siginfo_t;
+use std::io::Result;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+// Because each thread's timer notifies only that thread, they can all share a single signal.
+const PREEMPTION_SIGNAL: Signal = Signal::Alarm;
+
+/// Registration of the process-wide preemption signal handler, which remains installed as long as
+/// any thread holds one of these.
+pub struct SharedSignal (Signal);
+
+impl Deref for SharedSignal {
+	type Target = Signal;
 
-static NOTIFICATION_SIGNALS: [Signal; 16] = [
-	Signal::Alarm,
-	Signal::VirtualAlarm,
-	Signal::ProfilingTimer,
-	Signal::ProcessorLimit,
-	Signal::FilesystemLimit,
-	Signal::TerminalInput,
-	Signal::TerminalOutput,
-	Signal::PowerFailure,
-	Signal::User1,
-	Signal::User2,
-
-	// A stretch...
-	Signal::UrgentSocket,
-	Signal::Pollable,
-	Signal::Syscall,
-	Signal::FloatingPoint,
-	Signal::Hangup,
-	Signal::Child,
-];
-
-pub fn assign_signal() -> SyncResult<'static, Signal> {
+	fn deref(&self) -> &Self::Target {
+		let Self (signal) = self;
+		signal
+	}
+}
+
+impl Drop for SharedSignal {
+	fn drop(&mut self) {
+		let mut users = users().lock().unwrap_or_else(|poison| poison.into_inner());
+		*users -= 1;
+		if *users == 0 {
+			if let Err(or) = sigaction(**self, &(), None) {
+				eprintln!("libinger: unable to unregister signal handler: {}", or);
+			}
+		}
+	}
+}
+
+/// Number of kernel threads currently set up to receive the preemption signal.
+pub fn signal_users() -> usize {
+	*users().lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+pub fn assign_signal(handler: Handler) -> Result<SharedSignal> {
+	use libc::SA_RESTART;
+	use libc::SA_SIGINFO;
+	use signal::Action;
+	use signal::Set;
+	use signal::Sigaction;
+	use signal::Sigset;
+
+	let mut users = users().lock().unwrap_or_else(|poison| poison.into_inner());
+	if *users == 0 {
+		let sa = Sigaction::new(handler, Sigset::empty(), SA_SIGINFO | SA_RESTART);
+		sigaction(PREEMPTION_SIGNAL, &sa, None)?;
+	}
+	*users += 1;
+
+	Ok(SharedSignal (PREEMPTION_SIGNAL))
+}
+
+fn users() -> &'static Mutex<usize> {
 	use crate::compile_assert::assert_sync;
-	use crate::reusable::SyncPool;
 
-	use std::convert::TryInto;
-	use std::sync::atomic::AtomicUsize;
-	use std::sync::atomic::Ordering;
 	use std::sync::Once;
 
-	static mut SIGNALS: Option<SyncPool<Signal, Box<dyn Fn() -> Option<Signal> + Sync>>> = None;
+	static mut USERS: Option<Mutex<usize>> = None;
 	static INIT: Once = Once::new();
 	INIT.call_once(|| unsafe {
-		let free = AtomicUsize::new(0);
-		SIGNALS.replace(SyncPool::new(Box::new(move ||
-			NOTIFICATION_SIGNALS.get(free.fetch_add(1, Ordering::Relaxed)).copied()
-		)));
+		USERS.replace(Mutex::default());
 	});
 
-	let signals = unsafe {
-		SIGNALS.as_ref()
+	let users = unsafe {
+		USERS.as_ref()
 	}.unwrap();
-	assert_sync(&signals);
-	signals.try_into()
+	assert_sync(&users);
+	users
+}
+
+// Whatever handled segmentation faults before we did, so we can forward the ones that aren't ours.
+static mut FAULT_HANDLER: Option<Sigaction> = None;
+
+/// Install `handler` for segmentation faults, running on the alternate signal stack.  Faults it
+/// doesn't recognize should be passed along to `forward_fault()`.
+pub fn assign_fault_handler(handler: Handler) -> Result<()> {
+	use libc::SA_ONSTACK;
+	use libc::SA_SIGINFO;
+	use signal::Action;
+	use signal::Set;
+	use signal::Sigset;
+	use std::mem::zeroed;
+	use std::sync::Once;
+
+	static INIT: Once = Once::new();
+	let mut res = Ok(());
+	INIT.call_once(|| {
+		// Record the old handler first so we're ready to forward faults as soon as we get any.
+		let mut old = unsafe {
+			zeroed()
+		};
+		res = sigaction(Signal::Segfault, &(), Some(&mut old));
+		if res.is_ok() {
+			unsafe {
+				FAULT_HANDLER.replace(old);
+			}
+
+			let sa = Sigaction::new(handler, Sigset::empty(), SA_SIGINFO | SA_ONSTACK);
+			res = sigaction(Signal::Segfault, &sa, None);
+		}
+	});
+	res
+}
+
+/// Pass a segmentation fault along to the handler that was installed before ours.
+pub fn forward_fault(no: Signal, info: Option<&siginfo_t>, uc: Option<&mut ucontext_t>) {
+	use libc::SA_SIGINFO;
+	use libc::SIG_DFL;
+	use libc::SIG_IGN;
+	use std::mem::transmute;
+	use std::mem::zeroed;
+	use std::os::raw::c_int;
+
+	let old = unsafe {
+		FAULT_HANDLER.as_ref()
+	};
+	match old.map(|old| (old.sa_sigaction, old.sa_flags)) {
+	Some((SIG_DFL, _)) | Some((SIG_IGN, _)) | None => {
+		// Restore the default disposition so the faulting instruction will kill us when it
+		// reexecutes upon our return.
+		let mut sa: Sigaction = unsafe {
+			zeroed()
+		};
+		sa.sa_sigaction = SIG_DFL;
+		drop(sigaction(no, &sa, None));
+	},
+	Some((handler, flags)) => if flags & SA_SIGINFO != 0 {
+		let handler: Handler = unsafe {
+			transmute(handler)
+		};
+		handler(no, info, uc);
+	} else {
+		let handler: extern fn(c_int) = unsafe {
+			transmute(handler)
+		};
+		handler(no as _);
+	},
+	}
 }
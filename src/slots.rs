@@ -0,0 +1,190 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::reusable::ReusableLockFree;
+use crate::stacks::Stack;
+use crate::stacks::StackRef;
+use crate::tcb::ThreadControlBlock;
+
+use gotcha::Group;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread::ThreadId;
+
+// Number of slots sitting in the pool that still hold a thread-control block and stack.
+static FILLED: AtomicUsize = AtomicUsize::new(0);
+
+/// Where a timed function last ran, as a hint for where to resume it next.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Affinity {
+	/// The kernel thread that last launched or resumed it.
+	pub thread: ThreadId,
+	/// The CPU it was last running on, if the platform could tell us.
+	pub cpu: Option<usize>,
+}
+
+impl Affinity {
+	/// Where the calling kernel thread is running right now.
+	pub fn current() -> Self {
+		use libc::sched_getcpu;
+		use std::thread::current;
+
+		let cpu = unsafe {
+			sched_getcpu()
+		};
+		Self {
+			thread: current().id(),
+			cpu: if cpu < 0 { None } else { Some(cpu as _) },
+		}
+	}
+}
+
+/// Everything a timed function needs in order to run: a libset, a thread-control block for it,
+/// and an execution stack.  Slots are pooled as a unit, so a launch acquires all three at once and
+/// a canceled function's slot is renewed as a whole before anyone else can have it.
+///
+/// Trimming an idle slot frees its thread-control block and stack, but never its libset, which
+/// couldn't be recreated; they are reallocated the next time the slot is used.
+pub struct Slot {
+	group: Group,
+	tls: Option<ThreadControlBlock>,
+	stack: Option<Stack>,
+	// A stack from another size class, if the timed function asked for one.
+	custom: Option<ReusableLockFree<'static, Stack>>,
+	affinity: Option<Affinity>,
+	// Whether we're included in FILLED.
+	counted: bool,
+}
+
+impl Slot {
+	/// Allocate a new slot, or return `None` if any of its parts can't be had.  The libset is
+	/// created last, since it's the only part that can't be given back.
+	pub fn new() -> Option<Self> {
+		use super::STACK_SIZE_BYTES;
+
+		let stack = Stack::new(STACK_SIZE_BYTES).ok()?;
+		let tls = ThreadControlBlock::new()?;
+		let group = Group::new()?;
+		Some(Self {
+			group,
+			tls: Some(tls),
+			stack: Some(stack),
+			custom: None,
+			affinity: None,
+			counted: false,
+		})
+	}
+
+	pub fn group(&self) -> Group {
+		self.group
+	}
+
+	/// Get ready to run a new timed function on a stack of (at least) `size` bytes, refilling the
+	/// slot if it was trimmed while idle.
+	pub fn prepare(&mut self, size: usize) -> Result<()> {
+		use crate::stacks::alloc_stack;
+		use crate::stacks::stack_class;
+		use super::STACK_SIZE_BYTES;
+
+		if self.counted {
+			FILLED.fetch_sub(1, Ordering::Relaxed);
+			self.counted = false;
+		}
+
+		let size = stack_class(size).ok_or(Error::StackAlloc)?;
+		if self.tls.is_none() {
+			self.tls.replace(ThreadControlBlock::new().ok_or(Error::Tls(None))?);
+		}
+		if self.stack.is_none() {
+			self.stack.replace(Stack::new(STACK_SIZE_BYTES).map_err(|_| Error::StackAlloc)?);
+		}
+		if size != stack_class(STACK_SIZE_BYTES).unwrap() {
+			self.custom.replace(alloc_stack(size)?);
+		}
+
+		// The previous occupant's whereabouts aren't this function's.
+		self.affinity.take();
+		Ok(())
+	}
+
+	/// Borrow the thread-control block for installation.  Returns `None` while it's installed.
+	pub fn take_tls(&mut self) -> Option<ThreadControlBlock> {
+		self.tls.take()
+	}
+
+	pub fn replace_tls(&mut self, tls: ThreadControlBlock) {
+		self.tls.replace(tls);
+	}
+
+	/// The stack for the timed function to run on.  It stays in the slot, so the view mustn't
+	/// outlive the slot's current occupant.
+	pub fn stack(&mut self) -> StackRef {
+		if let Some(custom) = self.custom.as_mut() {
+			StackRef::from(&mut **custom)
+		} else {
+			// Can only be None if prepare() wasn't called first!
+			StackRef::from(self.stack.as_mut().unwrap())
+		}
+	}
+
+	/// The range of addresses beneath the stack that fault on access.
+	pub fn guard(&self) -> (usize, usize) {
+		self.custom.as_deref().or(self.stack.as_ref()).map(Stack::guard).unwrap_or_default()
+	}
+
+	pub fn affinity(&self) -> Option<Affinity> {
+		self.affinity
+	}
+
+	/// Record that the timed function just ran on the calling kernel thread.
+	pub fn touch(&mut self) {
+		self.affinity.replace(Affinity::current());
+	}
+
+	/// Whether the slot last ran on the same CPU as `here` (or, failing that, on the same
+	/// thread), making it the best one to hand out there.
+	pub fn is_local(&self, here: Affinity) -> bool {
+		self.affinity.map(|affinity| {
+			match (affinity.cpu, here.cpu) {
+			(Some(there), Some(here)) => there == here,
+			_ => affinity.thread == here.thread,
+			}
+		}).unwrap_or(false)
+	}
+
+	/// Tidy up as the slot is returned to the pool: release its stack memory and, if there are
+	/// already too many idle ones, its thread-control block and stack altogether.
+	pub fn recycle(&mut self) {
+		use crate::tunables::max_idle;
+
+		drop(self.custom.take());
+		if let Some(stack) = self.stack.as_mut() {
+			stack.release();
+		}
+		if self.tls.is_some() && self.stack.is_some() {
+			if FILLED.fetch_add(1, Ordering::Relaxed) < max_idle() {
+				self.counted = true;
+			} else {
+				FILLED.fetch_sub(1, Ordering::Relaxed);
+				self.hollow();
+			}
+		}
+	}
+
+	/// Free the thread-control block and stack of an idle slot, returning how many resources
+	/// were released.
+	pub fn hollow(&mut self) -> usize {
+		if self.counted {
+			FILLED.fetch_sub(1, Ordering::Relaxed);
+			self.counted = false;
+		}
+
+		let tls = self.tls.take().is_some() as usize;
+		let stack = self.stack.take().is_some() as usize;
+		tls + stack
+	}
+}
+
+/// Number of idle slots that still hold a thread-control block and stack.
+pub fn filled_slots() -> usize {
+	FILLED.load(Ordering::Relaxed)
+}
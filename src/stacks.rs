@@ -1,27 +1,70 @@
-use crate::reusable::ReusableSync;
+use crate::error::Error;
+use crate::reusable::LockFreePool;
+use crate::reusable::ReusableLockFree;
 
-use std::marker::PhantomData;
+use std::io::Result;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::ptr::NonNull;
+use std::result::Result as StdResult;
 use timetravel::stable::StableAddr;
 use timetravel::stable::StableMutAddr;
 
-pub fn alloc_stack() -> ReusableSync<'static, Box<[u8]>> {
-	use crate::compile_assert::assert_sync;
-	use crate::reusable::SyncPool;
-	use super::STACK_SIZE_BYTES;
+/// Size of the inaccessible region beneath each stack.  It's much bigger than a page so that a
+/// function with a large frame can't skip clean over it.
+const GUARD_SIZE_BYTES: usize = 64 * 1_024;
+
+/// Size of each kernel thread's alternate signal stack, if we have to provide one.
+const ALTSTACK_SIZE_BYTES: usize = 64 * 1_024;
+
+/// Base-2 logarithms of the smallest and largest stack size classes: 64 KiB and 1 GiB.
+const MIN_CLASS: u32 = 16;
+const MAX_CLASS: u32 = 30;
+
+/// Round `size` up to the size class that would be used to satisfy it, or `None` if it is too big.
+pub fn stack_class(size: usize) -> Option<usize> {
+	let size = size.max(1 << MIN_CLASS).checked_next_power_of_two()?;
+	if size <= 1 << MAX_CLASS {
+		Some(size)
+	} else {
+		None
+	}
+}
 
-	use gotcha::Group;
+/// Get a stack from the pool for the size class of `size`, which must satisfy `stack_class()`.
+pub fn alloc_stack(size: usize) -> StdResult<ReusableLockFree<'static, Stack>, Error> {
 	use std::convert::TryInto;
+
+	(&stacks()[index(size)]).try_into().map_err(|_| Error::StackAlloc)
+}
+
+/// Free idle stacks until at most `keep` remain in each size class, returning how many were freed.
+pub fn trim_stacks(keep: usize) -> usize {
+	stacks().iter().map(|stacks| stacks.trim(keep).unwrap_or_default()).sum()
+}
+
+/// Number of idle stacks across all size classes, other than those in idle slots.
+pub fn pooled_stacks() -> usize {
+	stacks().iter().map(|stacks| stacks.pooled()).sum()
+}
+
+type Builder = Box<dyn Fn() -> Option<Stack> + Send + Sync>;
+
+fn stacks() -> &'static [LockFreePool<Stack, Builder>] {
+	use crate::compile_assert::assert_sync;
+	use crate::tunables::max_idle;
+
 	use std::sync::Once;
 
-	static mut STACKS: Option<SyncPool<Box<[u8]>>> = None;
+	static mut STACKS: Option<Vec<LockFreePool<Stack, Builder>>> = None;
 	static INIT: Once = Once::new();
 	INIT.call_once(|| {
-		let stacks: fn() -> _ = || Some(vec![0; STACK_SIZE_BYTES].into_boxed_slice());
-		let stacks = SyncPool::new(stacks);
-		stacks.prealloc(Group::limit())
-			.expect("libinger: stack allocator lock was poisoned during init");
+		// Stacks of the default size class live in slots, so these pools only grow on demand.
+		let stacks: Vec<_> = (MIN_CLASS..=MAX_CLASS).map(|class| {
+			let size = 1 << class;
+			let stacks: Builder = Box::new(move || Stack::new(size).ok());
+			LockFreePool::new(stacks).with_recycler(Stack::release).with_max_idle(max_idle)
+		}).collect();
 		unsafe {
 			STACKS.replace(stacks);
 		}
@@ -31,32 +74,240 @@ pub fn alloc_stack() -> ReusableSync<'static, Box<[u8]>> {
 		STACKS.as_ref()
 	}.unwrap();
 	assert_sync(&stacks);
-	stacks.try_into().expect("libinger: stack allocator lock is poisoned")
+	stacks
+}
+
+fn index(size: usize) -> usize {
+	let size = stack_class(size).expect("libinger: stack size exceeds largest size class");
+	(size.trailing_zeros() - MIN_CLASS) as _
+}
+
+/// An anonymous memory mapping for use as an execution stack.  Since stacks grow downward, its
+/// lowest addresses form an inaccessible guard region that catches overflows.
+pub struct Stack {
+	base: NonNull<u8>,
+	len: usize,
 }
 
-pub struct DerefAdapter<'a, T> (T, PhantomData<&'a ()>);
+impl Stack {
+	pub fn new(size: usize) -> Result<Self> {
+		use libc::MAP_ANONYMOUS;
+		use libc::MAP_FAILED;
+		use libc::MAP_NORESERVE;
+		use libc::MAP_PRIVATE;
+		use libc::MAP_STACK;
+		use libc::PROT_NONE;
+		use libc::PROT_READ;
+		use libc::PROT_WRITE;
+		use libc::mmap;
+		use libc::mprotect;
+		use std::io::Error;
+		use std::ptr::null_mut;
 
-impl<T> From<T> for DerefAdapter<'_, T> {
-	fn from(t: T) -> Self {
-		Self (t, PhantomData::default())
+		let len = size + GUARD_SIZE_BYTES;
+		let base = unsafe {
+			mmap(
+				null_mut(),
+				len,
+				PROT_READ | PROT_WRITE,
+				MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | MAP_STACK,
+				-1,
+				0,
+			)
+		};
+		if base == MAP_FAILED {
+			Err(Error::last_os_error())?;
+		}
+
+		// From here on, dropping the instance will unmap the memory.
+		let this = Self {
+			base: NonNull::new(base as _).unwrap(),
+			len,
+		};
+		if unsafe {
+			mprotect(base, GUARD_SIZE_BYTES, PROT_NONE)
+		} != 0 {
+			Err(Error::last_os_error())?;
+		}
+		Ok(this)
+	}
+
+	/// Return the pages beneath the high-water mark to the operating system, so that they will
+	/// only be committed again if a subsequent user touches them.
+	pub fn release(&mut self) {
+		use crate::tunables::stack_high_water;
+
+		use libc::MADV_DONTNEED;
+		use libc::_SC_PAGESIZE;
+		use libc::madvise;
+		use libc::sysconf;
+
+		let page = unsafe {
+			sysconf(_SC_PAGESIZE)
+		} as usize;
+		let retain = stack_high_water().checked_add(page - 1).map(|bytes| bytes / page * page);
+		if let Some(len) = retain.and_then(|retain| self.len().checked_sub(retain)) {
+			if len != 0 && unsafe {
+				madvise(self.as_mut_ptr() as _, len, MADV_DONTNEED)
+			} != 0 {
+				eprintln!(
+					"libinger: unable to release stack memory: {}",
+					std::io::Error::last_os_error(),
+				);
+			}
+		}
+	}
+
+	/// The range of addresses that fault on access, beginning with the lowest.
+	pub fn guard(&self) -> (usize, usize) {
+		let base = self.base.as_ptr() as usize;
+		(base, base + GUARD_SIZE_BYTES)
 	}
 }
 
-impl<'a, T: Deref<Target = U>, U: Deref<Target = V> + 'a, V: ?Sized> Deref for DerefAdapter<'a, T> {
-	type Target = V;
+impl Deref for Stack {
+	type Target = [u8];
 
 	fn deref(&self) -> &Self::Target {
-		let Self (t, _) = self;
-		&***t
+		use std::slice::from_raw_parts;
+
+		unsafe {
+			from_raw_parts(self.base.as_ptr().add(GUARD_SIZE_BYTES), self.len - GUARD_SIZE_BYTES)
+		}
 	}
 }
 
-impl<'a, T: DerefMut<Target = U>, U: DerefMut<Target = V> + 'a, V: ?Sized> DerefMut for DerefAdapter<'a, T> {
+impl DerefMut for Stack {
 	fn deref_mut(&mut self) -> &mut Self::Target {
-		let Self (t, _) = self;
-		&mut ***t
+		use std::slice::from_raw_parts_mut;
+
+		unsafe {
+			from_raw_parts_mut(
+				self.base.as_ptr().add(GUARD_SIZE_BYTES),
+				self.len - GUARD_SIZE_BYTES,
+			)
+		}
+	}
+}
+
+impl Drop for Stack {
+	fn drop(&mut self) {
+		use libc::munmap;
+
+		if unsafe {
+			munmap(self.base.as_ptr() as _, self.len)
+		} != 0 {
+			eprintln!("libinger: unable to unmap stack: {}", std::io::Error::last_os_error());
+		}
+	}
+}
+
+unsafe impl Send for Stack {}
+unsafe impl Sync for Stack {}
+unsafe impl StableAddr for Stack {}
+unsafe impl StableMutAddr for Stack {}
+
+/// The current kernel thread's alternate signal stack, which lets us handle faults caused by a
+/// timed function exhausting its own stack.
+pub struct AltStack (Option<Stack>);
+
+impl AltStack {
+	/// Provide the calling kernel thread with an alternate signal stack, unless it already has
+	/// one (e.g., because the Rust runtime set one up to detect overflows of its own stacks).
+	pub fn new() -> Result<Self> {
+		use libc::SS_DISABLE;
+		use libc::stack_t;
+		use std::mem::zeroed;
+		use std::ptr::null;
+
+		let mut old: stack_t = unsafe {
+			zeroed()
+		};
+		sigaltstack(null(), &mut old)?;
+		if old.ss_flags & SS_DISABLE == 0 {
+			return Ok(Self (None));
+		}
+
+		let mut stack = Stack::new(ALTSTACK_SIZE_BYTES)?;
+		let new = stack_t {
+			ss_sp: stack.as_mut_ptr() as _,
+			ss_flags: 0,
+			ss_size: stack.len(),
+		};
+		sigaltstack(&new, &mut old)?;
+		Ok(Self (Some(stack)))
+	}
+}
+
+impl Drop for AltStack {
+	fn drop(&mut self) {
+		use libc::SS_DISABLE;
+		use libc::stack_t;
+		use std::ptr::null_mut;
+
+		if self.0.is_some() {
+			let disable = stack_t {
+				ss_sp: null_mut(),
+				ss_flags: SS_DISABLE,
+				ss_size: 0,
+			};
+			if let Err(or) = sigaltstack(&disable, null_mut()) {
+				eprintln!("libinger: unable to disable alternate signal stack: {}", or);
+			}
+		}
+	}
+}
+
+fn sigaltstack(new: *const libc::stack_t, old: *mut libc::stack_t) -> Result<()> {
+	use std::io::Error;
+
+	if unsafe {
+		libc::sigaltstack(new, old)
+	} == 0 {
+		Ok(())
+	} else {
+		Err(Error::last_os_error())
+	}
+}
+
+/// A view of a `Stack` that stays wherever it is, for use as an execution context's stack.  It
+/// doesn't own the memory, so the stack must outlive it.
+pub struct StackRef {
+	base: NonNull<u8>,
+	len: usize,
+}
+
+impl From<&mut Stack> for StackRef {
+	fn from(stack: &mut Stack) -> Self {
+		Self {
+			base: NonNull::new(stack.as_mut_ptr()).unwrap(),
+			len: stack.len(),
+		}
+	}
+}
+
+impl Deref for StackRef {
+	type Target = [u8];
+
+	fn deref(&self) -> &Self::Target {
+		use std::slice::from_raw_parts;
+
+		unsafe {
+			from_raw_parts(self.base.as_ptr(), self.len)
+		}
+	}
+}
+
+impl DerefMut for StackRef {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		use std::slice::from_raw_parts_mut;
+
+		unsafe {
+			from_raw_parts_mut(self.base.as_ptr(), self.len)
+		}
 	}
 }
 
-unsafe impl<'a, T: Deref<Target = U>, U: StableAddr + 'a> StableAddr for DerefAdapter<'a, T> {}
-unsafe impl<'a, T: DerefMut<Target = U>, U: StableMutAddr + 'a> StableMutAddr for DerefAdapter<'a, T> {}
+unsafe impl Send for StackRef {}
+unsafe impl StableAddr for StackRef {}
+unsafe impl StableMutAddr for StackRef {}
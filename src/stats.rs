@@ -0,0 +1,91 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+static LAUNCHES: AtomicU64 = AtomicU64::new(0);
+static COMPLETIONS: AtomicU64 = AtomicU64::new(0);
+static PANICS: AtomicU64 = AtomicU64::new(0);
+static OVERFLOWS: AtomicU64 = AtomicU64::new(0);
+static CANCELLATIONS: AtomicU64 = AtomicU64::new(0);
+static DROPS: AtomicU64 = AtomicU64::new(0);
+
+/// Points in a timed function's lifecycle that we keep count of.
+pub enum Event {
+	Launch,
+	Completion,
+	Panic,
+	Overflow,
+	Cancellation,
+	Drop,
+}
+
+pub fn record(event: Event) {
+	let counter = match event {
+	Event::Launch => &LAUNCHES,
+	Event::Completion => &COMPLETIONS,
+	Event::Panic => &PANICS,
+	Event::Overflow => &OVERFLOWS,
+	Event::Cancellation => &CANCELLATIONS,
+	Event::Drop => &DROPS,
+	};
+	counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Snapshot of the library's resource usage and cumulative activity.  Since the fields are read
+/// one at a time, they may be slightly inconsistent with each other if other threads are
+/// launching or finishing timed functions concurrently.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+	/// Timed functions that have been launched but not yet completed, abandoned, or dropped.
+	pub live: usize,
+	/// Libsets available to launch more timed functions, including reserved ones.
+	pub free_libsets: usize,
+	/// Libsets left behind by canceled timed functions that are still being reinitialized.
+	pub renewal_backlog: usize,
+	/// Idle stacks, across all size classes.
+	pub pooled_stacks: usize,
+	/// Idle thread-control blocks.
+	pub pooled_tcbs: usize,
+	/// Kernel threads currently set up to receive the preemption signal.
+	pub signals: usize,
+	/// Timed functions ever launched.
+	pub launches: u64,
+	/// Timed functions that ran to completion.
+	pub completions: u64,
+	/// Timed functions canceled partway through, whether by being dropped while paused or by
+	/// `cancel_unwinding()`.
+	pub cancellations: u64,
+	/// Timed functions that panicked.
+	pub panics: u64,
+	/// Timed functions abandoned because they overflowed their stacks.
+	pub overflows: u64,
+}
+
+/// Take a snapshot of the library's resource usage and cumulative activity.
+pub fn stats() -> Stats {
+	use crate::groups::libsets;
+	use crate::signals::signal_users;
+	use crate::slots::filled_slots;
+	use crate::stacks::pooled_stacks;
+
+	let launches = LAUNCHES.load(Ordering::Relaxed);
+	let completions = COMPLETIONS.load(Ordering::Relaxed);
+	let panics = PANICS.load(Ordering::Relaxed);
+	let overflows = OVERFLOWS.load(Ordering::Relaxed);
+	let cancellations = CANCELLATIONS.load(Ordering::Relaxed);
+	let drops = DROPS.load(Ordering::Relaxed);
+	let libsets = libsets();
+	let filled = filled_slots();
+	Stats {
+		live: launches.saturating_sub(drops) as _,
+		free_libsets: libsets.free + libsets.reserved,
+		renewal_backlog: libsets.renewing,
+		pooled_stacks: pooled_stacks() + filled,
+		pooled_tcbs: filled,
+		signals: signal_users(),
+		launches,
+		completions,
+		cancellations,
+		panics,
+		overflows,
+	}
+}
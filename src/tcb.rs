@@ -11,6 +11,7 @@ use std::io::Error;
 use std::io::Result;
 use std::os::raw::c_int;
 use std::os::raw::c_ulong;
+use std::result::Result as StdResult;
 
 #[must_use]
 pub struct ThreadControlBlock (Option<MaybeMut<'static>>);
@@ -22,7 +23,7 @@ impl ThreadControlBlock {
 		}
 	}
 
-	pub fn new() -> Self {
+	pub fn new() -> Option<Self> {
 		extern {
 			fn _dl_allocate_tls(_: Option<&mut TCB>) -> Option<&mut TCB>;
 		}
@@ -36,23 +37,27 @@ impl ThreadControlBlock {
 
 		let fs = unsafe {
 			_dl_allocate_tls(None)
-		}.expect("libinger: could not allocate thread-control block");
+		}?;
 		let auto: *mut _ = fs;
 		fs.tls_ptr = auto as _;
 		fs.self_ptr = auto as _;
 
 		let auto: *mut _ = auto as _;
-		Self (Some(MaybeMut::Mut(unsafe {
+		Some(Self (Some(MaybeMut::Mut(unsafe {
 			&mut *auto
-		})))
+		}))))
 	}
 
-	pub unsafe fn install(mut self, group: Group) -> Result<ThreadControlBlockGuard> {
-		let parent = unguarded_parent(self.install_unguarded(group.into()))?;
-		Ok(ThreadControlBlockGuard {
+	/// Install this TCB for the calling kernel thread, or hand it back if that fails.
+	pub unsafe fn install(mut self, group: Group)
+	-> StdResult<ThreadControlBlockGuard, (Error, Self)> {
+		match unguarded_parent(self.install_unguarded(group.into())) {
+		Ok(parent) => Ok(ThreadControlBlockGuard {
 			this: self,
 			parent,
-		})
+		}),
+		Err(or) => Err((or, self)),
+		}
 	}
 
 	unsafe fn install_unguarded(&mut self, group: Option<Group>) -> Result<Option<Self>> {
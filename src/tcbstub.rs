@@ -1,12 +1,14 @@
 use gotcha::Group;
+use std::io::Error;
 use std::io::Result;
+use std::result::Result as StdResult;
 
 #[must_use]
 pub struct ThreadControlBlock;
 
 impl ThreadControlBlock {
-	pub fn new() -> Self { Self }
-	pub unsafe fn install(self, _: Group) -> Result<ThreadControlBlockGuard> { Ok(ThreadControlBlockGuard) }
+	pub fn new() -> Option<Self> { Some(Self) }
+	pub unsafe fn install(self, _: Group) -> StdResult<ThreadControlBlockGuard, (Error, Self)> { Ok(ThreadControlBlockGuard) }
 }
 
 impl Drop for ThreadControlBlock {
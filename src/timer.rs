@@ -7,6 +7,7 @@ use libc::CLOCK_REALTIME_ALARM;
 use libc::CLOCK_THREAD_CPUTIME_ID;
 pub use libc::itimerspec;
 use libc::sigevent;
+use libc::timespec;
 use signal::Signal;
 use std::io::Error;
 use std::io::Result;
@@ -14,6 +15,7 @@ use std::mem::MaybeUninit;
 use std::os::raw::c_int;
 
 #[allow(dead_code)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Clock {
 	Boot = CLOCK_BOOTTIME as _,
 	BootAlarm = CLOCK_BOOTTIME_ALARM as _,
@@ -44,7 +46,6 @@ impl Sigevent {
 		Self (this)
 	}
 
-	#[allow(dead_code)]
 	pub fn thread_id(signal: Signal, thread: c_int) -> Self {
 		use libc::SIGEV_THREAD_ID;
 
@@ -63,6 +64,23 @@ impl Sigevent {
 	}
 }
 
+pub fn clock_gettime(clockid: Clock) -> Result<timespec> {
+	extern {
+		fn clock_gettime(_: c_int, _: *mut timespec) -> c_int;
+	}
+
+	let mut time = MaybeUninit::uninit();
+	if unsafe {
+		clock_gettime(clockid as _, time.as_mut_ptr())
+	} != 0 {
+		Err(Error::last_os_error())?;
+	}
+
+	Ok(unsafe {
+		time.assume_init()
+	})
+}
+
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct Timer (usize);
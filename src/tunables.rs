@@ -0,0 +1,191 @@
+use std::cell::Cell;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+/// Environment variable specifying the initial process-wide preemption quantum, in microseconds.
+const QUANTUM_VARIABLE: &str = "LIBINGER_QUANTUM";
+
+/// Environment variable that, if present, selects the one-shot timer mode at startup.
+const ONESHOT_VARIABLE: &str = "LIBINGER_ONESHOT";
+
+/// Environment variable specifying the initial stack high-water mark, in bytes.
+const HIGH_WATER_VARIABLE: &str = "LIBINGER_STACK_HIGH_WATER";
+
+/// Environment variable specifying the initial number of reserved libsets.
+const RESERVED_VARIABLE: &str = "LIBINGER_RESERVED_LIBSETS";
+
+/// Environment variable specifying the initial cap on idle stacks and thread-control blocks.
+const MAX_IDLE_VARIABLE: &str = "LIBINGER_MAX_IDLE";
+
+/// Environment variable that, if present, stops `cancel_unwinding()` from unwinding at startup.
+const NO_UNWIND_VARIABLE: &str = "LIBINGER_NO_UNWIND";
+
+/// Default number of bytes at the top of each idle stack that remain committed.
+const HIGH_WATER_BYTES: usize = 64 * 1_024;
+
+static QUANTUM: AtomicU64 = AtomicU64::new(0);
+static ONESHOT: AtomicBool = AtomicBool::new(false);
+static HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+static RESERVED: AtomicUsize = AtomicUsize::new(0);
+static MAX_IDLE: AtomicUsize = AtomicUsize::new(0);
+static UNWIND: AtomicBool = AtomicBool::new(false);
+
+// Only meaningful when accessed from outside any preemptible function, since each of these has its
+// own copy of all thread-local variables!
+thread_local! {
+	static THREAD_QUANTUM: Cell<Option<u64>> = Cell::default();
+}
+
+/// Get the preemption quantum, in microseconds, that applies to timed functions launched or
+/// resumed from the calling kernel thread.
+///
+/// This is the thread's own quantum if one has been set, or the process-wide one otherwise.
+pub fn quantum() -> u64 {
+	THREAD_QUANTUM.with(|quantum| quantum.get()).unwrap_or_else(|| {
+		init();
+		QUANTUM.load(Ordering::Relaxed)
+	})
+}
+
+/// Change the process-wide preemption quantum to `us`econds, which must be nonzero.
+///
+/// Each kernel thread's timer is reprogrammed the next time it resumes a timed function, unless
+/// that thread has its own quantum.
+pub fn set_quantum(us: u64) {
+	assert!(us != 0, "set_quantum(): quantum must be nonzero");
+	init();
+	QUANTUM.store(us, Ordering::Relaxed);
+}
+
+/// Override the preemption quantum for the calling kernel thread with `us`econds, or revert to
+/// the process-wide quantum if `None`.
+///
+/// The change takes effect the next time this thread resumes a timed function.  Must not be
+/// called from within a timed function.
+pub fn set_thread_quantum(us: Option<u64>) {
+	assert!(us != Some(0), "set_thread_quantum(): quantum must be nonzero");
+	THREAD_QUANTUM.with(|quantum| quantum.replace(us));
+}
+
+/// How the preemption timer decides when to check for timeouts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimerMode {
+	/// Fire once per quantum, checking each time whether the deadline has passed.
+	Periodic,
+	/// Fire exactly once, at the deadline, and remain disarmed while no timed function is running.
+	Oneshot,
+}
+
+/// Get the process-wide timer mode.
+pub fn timer_mode() -> TimerMode {
+	init();
+	if ONESHOT.load(Ordering::Relaxed) {
+		TimerMode::Oneshot
+	} else {
+		TimerMode::Periodic
+	}
+}
+
+/// Change the process-wide timer mode.
+///
+/// Each kernel thread's timer is reprogrammed the next time it resumes a timed function.
+pub fn set_timer_mode(mode: TimerMode) {
+	init();
+	ONESHOT.store(mode == TimerMode::Oneshot, Ordering::Relaxed);
+}
+
+/// Get the stack high-water mark, in bytes.
+pub fn stack_high_water() -> usize {
+	init();
+	HIGH_WATER.load(Ordering::Relaxed)
+}
+
+/// Change the stack high-water mark to `bytes`.
+///
+/// Whenever a stack is returned to its pool, the memory beneath this many bytes from its top is
+/// released back to the operating system, so idle stacks only stay resident up to this point.
+pub fn set_stack_high_water(bytes: usize) {
+	init();
+	HIGH_WATER.store(bytes, Ordering::Relaxed);
+}
+
+/// Get the number of libsets held back for high-priority launches.
+pub fn reserved_libsets() -> usize {
+	init();
+	RESERVED.load(Ordering::Relaxed)
+}
+
+/// Hold back `count` libsets for launches tagged `Priority::High`.
+///
+/// Normal-priority launches fail (or wait) whenever only this many libsets remain free.  Timed
+/// functions already holding a libset are unaffected.  High-priority launches can be made through
+/// `launch_with()`, `launch_blocking()`, or `launch_async()` by setting `LaunchOptions::priority`.
+pub fn set_reserved_libsets(count: usize) {
+	init();
+	RESERVED.store(count, Ordering::Relaxed);
+}
+
+/// Get the cap on the number of idle stacks (per size class) and thread-control blocks.
+pub fn max_idle() -> usize {
+	init();
+	MAX_IDLE.load(Ordering::Relaxed)
+}
+
+/// Cap the number of idle stacks (per size class) and thread-control blocks that are kept around
+/// for reuse at `count`; any more are freed as soon as their timed functions finish.
+///
+/// Lowering the cap doesn't free any resources that are already idle; see `trim_caches()`.
+pub fn set_max_idle(count: usize) {
+	init();
+	MAX_IDLE.store(count, Ordering::Relaxed);
+}
+
+/// Get whether `cancel_unwinding()` tries to unwind the stacks of the functions it cancels.
+pub fn unwinding() -> bool {
+	init();
+	UNWIND.load(Ordering::Relaxed)
+}
+
+/// Let `cancel_unwinding()` try to unwind the stacks of the functions it cancels, or not.
+///
+/// While disabled, it cancels them abruptly instead, just as though they had been dropped.
+pub fn set_unwinding(enabled: bool) {
+	init();
+	UNWIND.store(enabled, Ordering::Relaxed);
+}
+
+fn init() {
+	use super::QUANTUM_MICROSECS;
+
+	use std::env::var;
+	use std::env::var_os;
+	use std::sync::Once;
+
+	static INIT: Once = Once::new();
+	INIT.call_once(|| {
+		let quantum = var(QUANTUM_VARIABLE).ok()
+			.and_then(|quantum| quantum.parse().ok())
+			.filter(|&quantum| quantum != 0)
+			.unwrap_or(QUANTUM_MICROSECS);
+		QUANTUM.store(quantum, Ordering::Relaxed);
+		ONESHOT.store(var_os(ONESHOT_VARIABLE).is_some(), Ordering::Relaxed);
+
+		let high_water = var(HIGH_WATER_VARIABLE).ok()
+			.and_then(|high_water| high_water.parse().ok())
+			.unwrap_or(HIGH_WATER_BYTES);
+		HIGH_WATER.store(high_water, Ordering::Relaxed);
+
+		let reserved = var(RESERVED_VARIABLE).ok()
+			.and_then(|reserved| reserved.parse().ok())
+			.unwrap_or(0);
+		RESERVED.store(reserved, Ordering::Relaxed);
+
+		let max_idle = var(MAX_IDLE_VARIABLE).ok()
+			.and_then(|max_idle| max_idle.parse().ok())
+			.unwrap_or(usize::max_value());
+		MAX_IDLE.store(max_idle, Ordering::Relaxed);
+		UNWIND.store(var_os(NO_UNWIND_VARIABLE).is_none(), Ordering::Relaxed);
+	});
+}
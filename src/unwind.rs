@@ -0,0 +1,284 @@
+use timetravel::HandlerContext;
+
+/// Begins unwinding a canceled timed function's stack.  Never returns.
+pub type Raise = extern "C-unwind" fn() -> !;
+
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_INDIRECT: u8 = 0x80;
+
+// Where control goes instead of back into the interrupted code.  It finds the interrupted
+// instruction pointer, the interrupted stack pointer, and the function to call at the top of its
+// stack, in that order.  Its unwind table entry describes the interrupted frame as its caller, and
+// marks it as a signal frame so that the unwinder looks up the interrupted instruction itself
+// rather than the one before it.
+#[cfg(target_arch = "x86_64")]
+std::arch::global_asm!(
+	".text",
+	".p2align 4",
+	".globl libinger_unwind_trampoline",
+	".hidden libinger_unwind_trampoline",
+	".type libinger_unwind_trampoline, @function",
+	"libinger_unwind_trampoline:",
+	".cfi_startproc",
+	".cfi_signal_frame",
+	// CFA is the interrupted stack pointer: DW_CFA_def_cfa_expression(DW_OP_breg7 8, DW_OP_deref)
+	".cfi_escape 0x0f, 0x03, 0x77, 0x08, 0x06",
+	// Return address is the interrupted instruction: DW_CFA_expression(16, DW_OP_breg7 0)
+	".cfi_escape 0x10, 0x10, 0x02, 0x77, 0x00",
+	"call qword ptr [rsp + 16]",
+	"ud2",
+	".cfi_endproc",
+	".size libinger_unwind_trampoline, . - libinger_unwind_trampoline",
+);
+
+/// Arrange for the code interrupted by a signal to call `raise` upon return from the handler, as
+/// though it had done so itself.  Only does so if the unwinder could begin there, returning
+/// whether it did.  The stack must not extend below `floor`.
+///
+/// Most instructions aren't places a panic could come from, so this only works if the interrupted
+/// frame either has nothing to clean up or lies within its landing pads' coverage.  The frames
+/// above it must all be at call sites, hence unwindable in the usual way.
+#[cfg(target_arch = "x86_64")]
+pub fn redirect(uc: &mut HandlerContext, raise: Raise, floor: usize) -> bool {
+	use libc::REG_RIP;
+	use libc::REG_RSP;
+	use std::mem::size_of;
+
+	extern {
+		fn libinger_unwind_trampoline();
+	}
+
+	let regs = &mut uc.uc_mcontext.gregs;
+	let ip = regs[REG_RIP as usize] as usize;
+	let sp = regs[REG_RSP as usize] as usize;
+	if ! unwinds_at(ip) {
+		return false;
+	}
+
+	// The frame goes in the red zone, since only leaf functions use it and they have nothing to
+	// clean up.  Aligning it means the stack will be aligned when the trampoline makes its call.
+	let frame = sp.wrapping_sub(3 * size_of::<usize>()) & ! 0xf;
+	if frame < floor || frame > sp {
+		return false;
+	}
+	let frame = frame as *mut usize;
+	unsafe {
+		frame.write(ip);
+		frame.add(1).write(sp);
+		frame.add(2).write(raise as usize);
+	}
+
+	regs[REG_RSP as usize] = frame as _;
+	regs[REG_RIP as usize] = libinger_unwind_trampoline as *const () as _;
+	true
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn redirect(_: &mut HandlerContext, _: Raise, _: usize) -> bool {
+	false
+}
+
+/// Whether a panic could begin at instruction `ip`.
+fn unwinds_at(ip: usize) -> bool {
+	use std::os::raw::c_void;
+
+	extern {
+		fn _Unwind_Find_FDE(_: *const c_void, _: *mut Bases) -> *const u8;
+	}
+
+	let mut bases = Bases::default();
+	let fde = unsafe {
+		_Unwind_Find_FDE(ip as _, &mut bases)
+	};
+	if fde.is_null() {
+		return false;
+	}
+
+	match unsafe {
+		lsda(fde, &bases)
+	} {
+	// Without a language-specific data area, there's nothing here to clean up.
+	Some(None) => true,
+	Some(Some(lsda)) => unsafe {
+		covers(lsda, ip, &bases)
+	}.unwrap_or(false),
+	None => false,
+	}
+}
+
+/// Find the language-specific data area for a frame description entry, or `None` if we don't
+/// understand its encoding.
+unsafe fn lsda(fde: *const u8, bases: &Bases) -> Option<Option<usize>> {
+	use std::ffi::CStr;
+
+	let mut fde = Reader (fde);
+	if fde.fixed::<u32>() == u32::max_value() {
+		// 64-bit DWARF, which no one emits for .eh_frame.
+		return None;
+	}
+	let id = fde.0;
+	let mut cie = Reader (id.sub(fde.fixed::<u32>() as _));
+	if cie.fixed::<u32>() == u32::max_value() {
+		return None;
+	}
+	cie.fixed::<u32>();
+
+	let version = cie.u8();
+	let augmentation = CStr::from_ptr(cie.0 as _).to_bytes();
+	cie.0 = cie.0.add(augmentation.len() + 1);
+	if augmentation.first() != Some(&b'z') {
+		return Some(None);
+	}
+	cie.uleb();
+	cie.sleb();
+	if version == 1 {
+		cie.u8();
+	} else {
+		cie.uleb();
+	}
+	cie.uleb();
+
+	let mut fde_encoding = 0;
+	let mut lsda_encoding = DW_EH_PE_OMIT;
+	for letter in &augmentation[1..] {
+		match letter {
+		b'L' => lsda_encoding = cie.u8(),
+		b'R' => fde_encoding = cie.u8(),
+		b'P' => {
+			// Skip the personality routine without following it.
+			let encoding = cie.u8();
+			cie.pointer(encoding & ! DW_EH_PE_INDIRECT, bases)?;
+		},
+		b'S' | b'B' => (),
+		_ => return None,
+		}
+	}
+	if lsda_encoding == DW_EH_PE_OMIT {
+		return Some(None);
+	}
+
+	fde.pointer(fde_encoding, bases)?;
+	fde.pointer(fde_encoding & 0x0f, bases)?;
+	fde.uleb();
+	fde.pointer(lsda_encoding, bases).map(|lsda| Some(lsda).filter(|&lsda| lsda != 0))
+}
+
+/// Whether the call-site table in `lsda` has an entry covering `ip`.  If not, the personality
+/// routine would terminate the process instead of unwinding.
+unsafe fn covers(lsda: usize, ip: usize, bases: &Bases) -> Option<bool> {
+	let mut lsda = Reader (lsda as _);
+	let encoding = lsda.u8();
+	if encoding != DW_EH_PE_OMIT {
+		lsda.pointer(encoding, bases)?;
+	}
+	if lsda.u8() != DW_EH_PE_OMIT {
+		lsda.uleb();
+	}
+
+	let encoding = lsda.u8();
+	let len = lsda.uleb();
+	let end = lsda.0.add(len);
+	while lsda.0 < end {
+		let start = bases.func + lsda.pointer(encoding, bases)?;
+		let len = lsda.pointer(encoding, bases)?;
+		lsda.pointer(encoding, bases)?;
+		lsda.uleb();
+		if start <= ip && ip < start + len {
+			return Some(true);
+		}
+	}
+	Some(false)
+}
+
+#[derive(Default)]
+#[repr(C)]
+struct Bases {
+	text: usize,
+	data: usize,
+	func: usize,
+}
+
+struct Reader (*const u8);
+
+impl Reader {
+	unsafe fn fixed<T: Copy>(&mut self) -> T {
+		use std::mem::size_of;
+
+		let Self (cursor) = self;
+		let value = (*cursor as *const T).read_unaligned();
+		*cursor = cursor.add(size_of::<T>());
+		value
+	}
+
+	unsafe fn u8(&mut self) -> u8 {
+		self.fixed()
+	}
+
+	unsafe fn uleb(&mut self) -> usize {
+		let mut value = 0;
+		let mut shift = 0;
+		loop {
+			let byte = self.u8();
+			if shift < usize::max_value().count_ones() {
+				value |= ((byte & 0x7f) as usize) << shift;
+			}
+			shift += 7;
+			if byte & 0x80 == 0 {
+				break value;
+			}
+		}
+	}
+
+	unsafe fn sleb(&mut self) -> isize {
+		let mut value = 0;
+		let mut shift = 0;
+		loop {
+			let byte = self.u8();
+			if shift < usize::max_value().count_ones() {
+				value |= ((byte & 0x7f) as isize) << shift;
+			}
+			shift += 7;
+			if byte & 0x80 == 0 {
+				if shift < usize::max_value().count_ones() && byte & 0x40 != 0 {
+					value |= -1 << shift;
+				}
+				break value;
+			}
+		}
+	}
+
+	/// Read a pointer with the specified DWARF exception-handling encoding, or return `None` if
+	/// we don't support it.
+	unsafe fn pointer(&mut self, encoding: u8, bases: &Bases) -> Option<usize> {
+		let field = self.0 as usize;
+		let value = match encoding & 0x0f {
+		0x00 => self.fixed::<usize>(),
+		0x01 => self.uleb(),
+		0x02 => self.fixed::<u16>() as _,
+		0x03 => self.fixed::<u32>() as _,
+		0x04 => self.fixed::<u64>() as _,
+		0x09 => self.sleb() as _,
+		0x0a => self.fixed::<i16>() as _,
+		0x0b => self.fixed::<i32>() as _,
+		0x0c => self.fixed::<i64>() as _,
+		_ => return None,
+		};
+		if value == 0 {
+			return Some(0);
+		}
+
+		let value = value.wrapping_add(match encoding & 0x70 {
+		0x00 => 0,
+		0x10 => field,
+		0x20 => bases.text,
+		0x30 => bases.data,
+		0x40 => bases.func,
+		_ => return None,
+		});
+		if encoding & DW_EH_PE_INDIRECT != 0 {
+			Some(*(value as *const usize))
+		} else {
+			Some(value)
+		}
+	}
+}
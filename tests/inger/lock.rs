@@ -2,10 +2,23 @@ use std::sync::MutexGuard;
 
 pub fn exclusive<T>(fun: fn() -> T) {
 	let lock = lock();
+	// Don't let libsets from the previous test's canceled functions count against this one.
+	renewed();
 	fun();
 	drop(lock);
 }
 
+/// Wait for the libsets of any canceled timed functions to be returned to the pool.
+pub fn renewed() {
+	use inger::stats;
+	use std::thread::sleep;
+	use std::time::Duration;
+
+	while stats().renewal_backlog != 0 {
+		sleep(Duration::from_millis(1));
+	}
+}
+
 fn lock() -> MutexGuard<'static, ()> {
 	use std::sync::Mutex;
 	use std::sync::Once;
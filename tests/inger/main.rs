@@ -5,10 +5,44 @@ extern crate test;
 mod lock;
 
 use lock::exclusive;
+use lock::renewed;
 
+use inger::Budget;
+use inger::BudgetClock;
+use inger::cancel_unwinding;
+use inger::checkpoint;
+use inger::is_canceled;
+use inger::is_cancelled;
 use inger::launch;
+use inger::launch_async;
+use inger::launch_blocking;
+use inger::launch_for;
+use inger::launch_on;
+use inger::launch_queue_depth;
+use inger::launch_until;
+use inger::launch_with;
+use inger::LaunchOptions;
+use inger::Linger;
+use inger::libsets;
 use inger::nsnow;
+use inger::on_cancel;
+use inger::pause;
+use inger::PoisonReason;
+use inger::Priority;
+use inger::quantum;
 use inger::resume;
+use inger::resume_for;
+use inger::resume_until;
+use inger::stack_high_water;
+use inger::stats;
+use inger::trim_caches;
+use inger::try_launch;
+use inger::set_reserved_libsets;
+use inger::set_stack_high_water;
+use inger::set_thread_quantum;
+use inger::set_timer_mode;
+use inger::set_unwinding;
+use inger::TimerMode;
 #[cfg(bench)]
 use test::Bencher;
 
@@ -55,7 +89,6 @@ fn launch_panic_outer() {
 	);
 }
 
-#[ignore]
 #[should_panic(expected = "PASS")]
 #[test]
 fn launch_panic_inner() {
@@ -65,7 +98,19 @@ fn launch_panic_inner() {
 	);
 }
 
-#[ignore]
+#[test]
+fn resume_poison() {
+	exclusive(|| {
+		use std::panic::AssertUnwindSafe;
+		use std::panic::catch_unwind;
+
+		let mut lingerer = launch(|| panic!("poison"), 0).unwrap();
+		assert!(catch_unwind(AssertUnwindSafe(|| drop(resume(&mut lingerer, 1_000)))).is_err());
+		assert!(if let Linger::Poison = lingerer { true } else { false });
+		assert!(lingerer.poisoned() == Some(PoisonReason::Panic));
+	});
+}
+
 #[test]
 fn launch_completions() {
 	exclusive(||
@@ -73,7 +118,6 @@ fn launch_completions() {
 	);
 }
 
-#[ignore]
 #[test]
 fn launch_continuations() {
 	exclusive(|| {
@@ -116,6 +160,192 @@ fn resume_completion_repeat() {
 	});
 }
 
+#[test]
+fn quantum_thread() {
+	exclusive(|| {
+		let process = quantum();
+		set_thread_quantum(Some(process * 10));
+		assert_eq!(quantum(), process * 10);
+		assert!(launch(|| timeout(1_000_000), 10).unwrap().is_continuation());
+
+		set_thread_quantum(None);
+		assert_eq!(quantum(), process);
+		assert!(launch(|| timeout(1_000_000), 10).unwrap().is_continuation());
+	});
+}
+
+#[test]
+fn timer_oneshot() {
+	exclusive(|| {
+		set_timer_mode(TimerMode::Oneshot);
+		assert!(launch(|| (), 1_000).unwrap().is_completion());
+		assert!(launch(|| timeout(1_000_000), 10).unwrap().is_continuation());
+		set_timer_mode(TimerMode::Periodic);
+	});
+}
+
+#[test]
+fn launch_thread_cpu() {
+	exclusive(|| {
+		use std::thread::sleep;
+		use std::time::Duration;
+
+		let nap = || sleep(Duration::from_millis(10));
+		let budget = Duration::from_millis(1);
+		assert!(launch_on(nap, budget, BudgetClock::Wall).unwrap().is_continuation());
+		assert!(launch_on(nap, budget, BudgetClock::ThreadCpu).unwrap().is_completion());
+		assert!(launch_on(|| timeout(1_000_000), budget, BudgetClock::ThreadCpu).unwrap().is_continuation());
+	});
+}
+
+#[test]
+fn launch_budget() {
+	exclusive(|| {
+		use std::sync::atomic::AtomicBool;
+		use std::sync::atomic::Ordering;
+		use std::time::Duration;
+		use std::time::Instant;
+
+		assert!(launch_for(|| (), Duration::from_millis(1)).unwrap().is_completion());
+		assert!(launch_for(|| timeout(1_000_000), Duration::from_micros(10)).unwrap().is_continuation());
+		assert!(launch_for(|| timeout(1_000), Budget::Unlimited).unwrap().is_completion());
+
+		let run = AtomicBool::new(false);
+		let mut prep = launch_for(|| run.store(true, Ordering::Relaxed), Budget::SetupOnly).unwrap();
+		resume_for(&mut prep, Budget::SetupOnly).unwrap();
+		assert!(! run.load(Ordering::Relaxed));
+		resume_for(&mut prep, Instant::now() + Duration::from_millis(1)).unwrap();
+		assert!(run.load(Ordering::Relaxed));
+		assert!(prep.is_completion());
+	});
+}
+
+#[test]
+fn resume_deadline() {
+	exclusive(|| {
+		use std::time::Duration;
+		use std::time::Instant;
+
+		let deadline = Instant::now() + Duration::from_millis(5);
+		let mut lock = launch_until(|| timeout(1_000_000), Instant::now() + Duration::from_micros(10)).unwrap();
+		assert!(lock.is_continuation());
+		let mut consumed = lock.consumed().unwrap();
+		while lock.is_continuation() && Instant::now() < deadline {
+			resume_until(&mut lock, deadline).unwrap();
+			assert!(lock.consumed().unwrap() >= consumed);
+			consumed = lock.consumed().unwrap();
+		}
+		assert!(lock.is_continuation());
+		assert!(Instant::now() >= deadline);
+		assert!(consumed >= Duration::from_millis(4));
+		match lock.remaining() {
+		Some(Budget::Timeout(remaining)) => assert!(remaining == Duration::default()),
+		_ => panic!("remaining(): expected an expired timeout"),
+		}
+	});
+}
+
+#[test]
+fn resume_until_thread_cpu() {
+	exclusive(|| {
+		use std::thread::sleep;
+		use std::time::Duration;
+		use std::time::Instant;
+
+		let mut lingerer = launch_on(|| {
+			pause();
+			sleep(Duration::from_millis(10));
+		}, Budget::Unlimited, BudgetClock::ThreadCpu).unwrap();
+		assert!(lingerer.yielded());
+
+		// Sleeping uses next to no CPU time, so it fits within the deadline on the function's
+		// own clock even though it wouldn't on the monotonic one.
+		resume_until(&mut lingerer, Instant::now() + Duration::from_millis(1)).unwrap();
+		assert!(lingerer.is_completion(), "resume_until(): replaced the function's budget clock");
+	});
+}
+
+#[test]
+fn resume_usage() {
+	exclusive(|| {
+		let mut lock = launch(|| {
+			pause();
+			timeout(1_000_000);
+		}, 1_000).unwrap();
+		assert!(lock.yielded());
+		resume(&mut lock, 10).unwrap();
+		resume(&mut lock, 10).unwrap();
+
+		let usage = lock.usage().unwrap();
+		assert!(usage.resumptions == 3);
+		assert!(usage.yields == 1);
+		assert!(usage.preemptions == 2);
+		assert!(usage.runtime >= usage.deferred);
+	});
+}
+
+#[test]
+fn launch_stack() {
+	exclusive(|| {
+		assert!(launch_with(|| (), Budget::Unlimited, stack(64 * 1_024)).unwrap().is_completion());
+		assert!(launch_with(|| recurse(2_048), Budget::Unlimited, stack(16 * 1_024 * 1_024)).unwrap().is_completion());
+		assert!(launch_with(|| (), Budget::Unlimited, stack(usize::max_value())).is_err());
+	});
+}
+
+#[test]
+fn launch_overflow() {
+	exclusive(|| {
+		let lock = launch_with(|| recurse(2_048), Budget::Unlimited, stack(64 * 1_024)).unwrap();
+		assert!(if let Linger::Overflowed = lock { true } else { false });
+		assert!(lock.poisoned() == Some(PoisonReason::StackOverflow));
+		assert!(launch_with(|| recurse(8), Budget::Unlimited, stack(64 * 1_024)).unwrap().is_completion());
+	});
+}
+
+#[test]
+fn stack_release() {
+	exclusive(|| {
+		use libc::_SC_PAGESIZE;
+		use libc::mincore;
+		use libc::sysconf;
+
+		const FRAME: usize = 256 * 1_024;
+
+		let page = unsafe {
+			sysconf(_SC_PAGESIZE)
+		} as usize;
+		let high_water = stack_high_water();
+		set_stack_high_water(0);
+		let lock = launch_with(move || {
+			// Commit every page of a big frame, then report where it was.
+			let frame = [1u8; FRAME];
+			let mut sum = 0u8;
+			for byte in frame.iter().step_by(page) {
+				sum = sum.wrapping_add(unsafe {
+					std::ptr::read_volatile(byte)
+				});
+			}
+			assert!(sum != 0);
+			frame.as_ptr() as usize
+		}, Budget::Unlimited, stack(1_024 * 1_024)).unwrap();
+		set_stack_high_water(high_water);
+
+		if let Linger::Completion(frame) = lock {
+			// By now, the stack is back in its pool and none of the frame should be resident.
+			let start = frame / page * page;
+			let len = (frame + FRAME - start + page - 1) / page * page;
+			let mut resident = vec![0u8; len / page];
+			assert!(unsafe {
+				mincore(start as _, len, resident.as_mut_ptr())
+			} == 0);
+			assert!(resident.iter().all(|page| page & 1 == 0), "stack memory wasn't released");
+		} else {
+			unreachable!("function didn't complete");
+		}
+	});
+}
+
 #[test]
 fn setup_only() {
 	use std::sync::atomic::AtomicBool;
@@ -130,17 +360,458 @@ fn setup_only() {
 	});
 }
 
-#[should_panic(expected = "launch(): too many active timed functions: None")]
+#[test]
+fn resume_zero() {
+	use std::sync::atomic::AtomicBool;
+	use std::sync::atomic::Ordering;
+
+	exclusive(|| {
+		let run = AtomicBool::new(false);
+		let mut prep = launch(|| run.store(true, Ordering::Relaxed), 0).unwrap();
+		resume(&mut prep, 0).unwrap();
+		assert!(run.load(Ordering::Relaxed), "resume(): didn't run function with zero budget");
+	});
+}
+
 #[test]
 fn launch_toomany() {
+	exclusive(|| {
+		use inger::Error;
+		use std::collections::LinkedList;
+
+		let mut orphans = LinkedList::default();
+		let or = loop {
+			match launch(|| timeout(1_000_000), 0) {
+			Ok(orphan) => orphans.push_back(orphan),
+			Err(or) => break or,
+			}
+		};
+		assert!(if let Error::NoLibset = or { true } else { false }, "launch(): {}", or);
+
+		// Shedding load should make room again, once the libset has been renewed.
+		orphans.pop_back();
+		renewed();
+		assert!(launch(|| (), 1_000).unwrap().is_completion());
+	});
+}
+
+#[test]
+fn launch_wait() {
+	exclusive(|| {
+		use std::collections::LinkedList;
+		use std::future::Future;
+		use std::task::Context;
+		use std::task::Poll;
+		use std::task::RawWaker;
+		use std::task::RawWakerVTable;
+		use std::task::Waker;
+		use std::time::Duration;
+
+		fn noop(_: *const ()) {}
+		fn clone(_: *const ()) -> RawWaker {
+			RawWaker::new(std::ptr::null(), &VTABLE)
+		}
+		static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+		let mut orphans = LinkedList::default();
+		while let Ok(orphan) = launch(|| (), 0) {
+			orphans.push_back(orphan);
+		}
+		let options = stack(64 * 1_024);
+		let timeout = Some(Duration::from_millis(1));
+		assert!(launch_blocking(|| (), Budget::Unlimited, options, timeout).is_err());
+
+		let waker = unsafe {
+			Waker::from_raw(clone(std::ptr::null()))
+		};
+		let mut context = Context::from_waker(&waker);
+		let mut waiter = Box::pin(launch_async(|| (), Budget::Unlimited, options));
+		assert!(launch_queue_depth() == 1);
+		assert!(waiter.as_mut().poll(&mut context).is_pending());
+
+		// Nobody may cut in front of a caller who is already waiting.
+		orphans.pop_back();
+		assert!(launch(|| (), 1_000).is_err());
+		if let Poll::Ready(lock) = waiter.as_mut().poll(&mut context) {
+			assert!(lock.unwrap().is_completion());
+		} else {
+			panic!("launch_async(): still pending after a libset was freed");
+		}
+		assert!(launch_queue_depth() == 0);
+	});
+}
+
+#[test]
+fn launch_reserved() {
 	exclusive(|| {
 		use std::collections::LinkedList;
+		use std::time::Duration;
 
+		set_reserved_libsets(1);
 		let mut orphans = LinkedList::default();
-		loop {
-			orphans.push_back(launch(|| timeout(1_000_000), 0).unwrap());
-			// Lock becomes poisoned.
+		while let Ok(orphan) = launch(|| (), 0) {
+			orphans.push_back(orphan);
+		}
+		let usage = libsets();
+		assert!(usage.free == 0);
+		assert!(usage.reserved == 1);
+
+		let timeout = Some(Duration::from_millis(1));
+		assert!(launch_blocking(|| (), Budget::SetupOnly, stack(64 * 1_024), timeout).is_err());
+		let urgent = launch_blocking(|| (), Budget::SetupOnly, high(), timeout).unwrap();
+		drop(urgent);
+
+		let urgent = launch_with(|| (), Budget::SetupOnly, high()).unwrap();
+		assert!(libsets().reserved == 0);
+		assert!(launch_with(|| (), Budget::SetupOnly, high()).is_err());
+
+		drop(urgent);
+		drop(orphans);
+		set_reserved_libsets(0);
+		assert!(libsets().in_use == 0);
+	});
+}
+
+#[test]
+fn launch_reserved_queued() {
+	exclusive(|| {
+		use std::collections::LinkedList;
+		use std::thread::sleep;
+		use std::thread::spawn;
+		use std::time::Duration;
+
+		set_reserved_libsets(1);
+		let mut orphans = LinkedList::default();
+		while let Ok(orphan) = launch(|| (), 0) {
+			orphans.push_back(orphan);
+		}
+
+		// Park a normal-priority launch in line, where it stays until an unreserved libset frees up.
+		let waiter = spawn(move ||
+			launch_blocking(|| (), Budget::SetupOnly, stack(64 * 1_024), None).is_ok()
+		);
+		while launch_queue_depth() == 0 {
+			sleep(Duration::from_millis(1));
+		}
+
+		let urgent = launch_with(|| (), Budget::SetupOnly, high()).unwrap();
+		drop(urgent);
+		let timeout = Some(Duration::from_millis(1));
+		let urgent = launch_blocking(|| (), Budget::SetupOnly, high(), timeout).unwrap();
+		drop(urgent);
+		assert!(launch_queue_depth() == 1);
+
+		drop(orphans);
+		assert!(waiter.join().unwrap());
+		set_reserved_libsets(0);
+		assert!(libsets().in_use == 0);
+	});
+}
+
+#[test]
+fn launch_stats() {
+	exclusive(|| {
+		let before = stats();
+		assert!(launch(|| (), 1_000).unwrap().is_completion());
+		let paused = launch(|| timeout(1_000_000), 10).unwrap();
+		assert!(stats().live == before.live + 1);
+		assert!(stats().free_libsets == before.free_libsets - 1);
+		drop(paused);
+
+		let after = stats();
+		assert!(after.live == before.live);
+		assert!(after.launches == before.launches + 2);
+		assert!(after.completions == before.completions + 1);
+		assert!(after.cancellations == before.cancellations + 1);
+		assert!(after.panics == before.panics);
+
+		// Failing to launch isn't a cancellation.
+		assert!(launch_with(|| (), Budget::Unlimited, stack(usize::max_value())).is_err());
+		assert!(stats().cancellations == after.cancellations);
+	});
+}
+
+#[test]
+fn launch_trimmed() {
+	exclusive(|| {
+		assert!(launch(|| (), 1_000).unwrap().is_completion());
+		trim_caches();
+		assert!(stats().pooled_stacks == 0);
+		assert!(stats().pooled_tcbs == 0);
+		assert!(launch(|| (), 1_000).unwrap().is_completion());
+		assert!(stats().pooled_stacks == 1);
+	});
+}
+
+#[test]
+fn launch_concurrent() {
+	exclusive(|| {
+		use std::thread::spawn;
+
+		let before = stats().pooled_tcbs;
+		let threads: Vec<_> = (0..4).map(|_| spawn(||
+			for _ in 0..100 {
+				assert!(launch(|| (), 1_000).unwrap().is_completion());
+			}
+		)).collect();
+		for thread in threads {
+			thread.join().unwrap();
 		}
+		assert!(stats().pooled_tcbs >= before);
+		assert!(stats().pooled_tcbs <= before + 4);
+	});
+}
+
+#[test]
+fn launch_affinity() {
+	exclusive(|| {
+		use std::thread::current;
+
+		let lingerer = launch(|| timeout(1_000_000), 10).unwrap();
+		let affinity = lingerer.affinity().unwrap();
+		assert!(affinity.thread == current().id());
+
+		let stacks = stats().pooled_stacks;
+		drop(lingerer);
+		renewed();
+		assert!(stats().pooled_stacks == stacks + 1);
+		assert!(launch(|| (), 1_000).unwrap().affinity().is_none());
+	});
+}
+
+#[test]
+fn launch_renewal() {
+	exclusive(|| {
+		let free = libsets().free;
+		drop(launch(|| timeout(1_000_000), 10).unwrap());
+		renewed();
+		assert!(libsets().free == free);
+		assert!(libsets().in_use == 0);
+	});
+}
+
+#[test]
+fn cancel_unwinding_drops() {
+	exclusive(|| {
+		use std::sync::Arc;
+		use std::sync::atomic::AtomicBool;
+		use std::sync::atomic::Ordering;
+		use std::time::Duration;
+
+		struct Flag (Arc<AtomicBool>);
+
+		impl Drop for Flag {
+			fn drop(&mut self) {
+				let Self (flag) = self;
+				flag.store(true, Ordering::Relaxed);
+			}
+		}
+
+		let dropped = Arc::new(AtomicBool::new(false));
+		let flag = Flag (dropped.clone());
+		// Stay out of nonpreemptible code, so the preemption lands somewhere we can unwind from.
+		let lingerer = launch(move || {
+			let _flag = flag;
+			loop {
+				spin();
+			}
+		}, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+		assert!(! dropped.load(Ordering::Relaxed));
+		assert!(cancel_unwinding(lingerer, Duration::from_millis(10)));
+		assert!(dropped.load(Ordering::Relaxed));
+	});
+}
+
+#[test]
+fn cancel_unwinding_plain() {
+	exclusive(|| {
+		use std::time::Duration;
+
+		// With nothing to drop, its frames have no cleanups for the unwinder to find.
+		let lingerer = launch(|| loop {
+			spin();
+		}, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+		assert!(cancel_unwinding(lingerer, Duration::from_millis(10)));
+	});
+}
+
+#[test]
+fn cancel_unwinding_paused() {
+	exclusive(|| {
+		use std::time::Duration;
+
+		// It only ever stops in nonpreemptible code, where it can't be unwound.
+		let lingerer = launch(|| loop {
+			pause();
+		}, 1_000).unwrap();
+		assert!(lingerer.yielded());
+
+		let before = stats();
+		assert!(! cancel_unwinding(lingerer, Duration::from_millis(10)));
+		renewed();
+		assert!(libsets().in_use == 0);
+		assert!(stats().cancellations == before.cancellations + 1);
+	});
+}
+
+#[test]
+fn cancel_unwinding_disabled() {
+	exclusive(|| {
+		use std::sync::Arc;
+		use std::sync::atomic::AtomicBool;
+		use std::sync::atomic::Ordering;
+		use std::time::Duration;
+
+		let dropped = Arc::new(AtomicBool::new(false));
+		let flag = dropped.clone();
+		let lingerer = launch(move || {
+			on_cancel(move || flag.store(true, Ordering::Relaxed));
+			loop {
+				spin();
+			}
+		}, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+
+		set_unwinding(false);
+		let unwound = cancel_unwinding(lingerer, Duration::from_millis(10));
+		set_unwinding(true);
+		assert!(! unwound);
+		assert!(dropped.load(Ordering::Relaxed), "cancel_unwinding(): didn't cancel abruptly");
+	});
+}
+
+#[test]
+fn cancel_hooks() {
+	exclusive(|| {
+		use std::sync::Arc;
+		use std::sync::Mutex;
+
+		let order = Arc::new(Mutex::new(Vec::new()));
+		let hooks = order.clone();
+		let mut lingerer = launch(move || {
+			on_cancel(|| panic!("cancellation hook"));
+			for hook in 0..3 {
+				let order = hooks.clone();
+				on_cancel(move || order.lock().unwrap().push(hook));
+				pause();
+			}
+		}, 1_000).unwrap();
+		resume(&mut lingerer, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+		assert!(order.lock().unwrap().is_empty());
+
+		drop(lingerer);
+		assert!(*order.lock().unwrap() == [1, 0]);
+	});
+}
+
+#[test]
+fn cancel_token() {
+	exclusive(|| {
+		let mut lingerer = launch(|| {
+			while checkpoint().is_ok() {
+				pause();
+			}
+			assert!(is_canceled());
+			assert!(is_cancelled());
+		}, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+
+		let token = lingerer.cancellation_token().unwrap();
+		resume(&mut lingerer, 1_000).unwrap();
+		assert!(lingerer.is_continuation());
+		assert!(! is_canceled());
+
+		token.cancel();
+		resume(&mut lingerer, 1_000).unwrap();
+		assert!(lingerer.is_completion(), "canceled function didn't wind down");
+
+		// The next function to get the same slot mustn't see the old token.
+		assert!(launch(|| assert!(! is_canceled()), 1_000).unwrap().is_completion());
+	});
+}
+
+#[test]
+fn try_launch_panic() {
+	exclusive(|| {
+		let lingerer = try_launch(|| panic!("captured"), Budget::Unlimited, stack(64 * 1_024)).unwrap();
+		assert!(lingerer.poisoned() == Some(PoisonReason::Panic));
+		if let Linger::Panicked(panic) = lingerer {
+			assert!(panic.downcast_ref::<&str>() == Some(&"captured"));
+		} else {
+			unreachable!("panic wasn't captured");
+		}
+	});
+}
+
+#[test]
+fn launch_nested_clamped() {
+	exclusive(|| {
+		let mut outer = launch(|| {
+			let inner = launch_for(|| timeout(1_000_000), Budget::Unlimited).unwrap();
+			inner.is_continuation()
+		}, 1_000).unwrap();
+		assert!(outer.is_continuation());
+
+		resume(&mut outer, u64::max_value()).unwrap();
+		if let Linger::Completion(clamped) = outer {
+			assert!(clamped, "inner function outlasted outer one");
+		} else {
+			unreachable!("outer function didn't complete");
+		}
+	});
+}
+
+#[test]
+fn launch_nested_canceled() {
+	exclusive(|| {
+		let outer = launch(|| {
+			let inner = launch(|| timeout(1_000_000), 10).unwrap();
+			assert!(inner.is_continuation());
+			timeout(1_000_000);
+			drop(inner);
+		}, 1_000).unwrap();
+		assert!(outer.is_continuation());
+		assert!(libsets().in_use == 2);
+
+		let before = stats();
+		drop(outer);
+		renewed();
+		assert!(libsets().in_use == 0);
+		assert!(stats().live == before.live - 2);
+		assert!(stats().cancellations == before.cancellations + 2);
+	});
+}
+
+#[test]
+fn resume_migrate() {
+	exclusive(|| {
+		use std::thread::current;
+		use std::thread::spawn;
+
+		let mut lingerer = launch(|| {
+			for _ in 0..4 {
+				timeout(1_000);
+			}
+		}, 10).unwrap();
+		assert!(lingerer.is_continuation());
+
+		while lingerer.is_continuation() {
+			let before = lingerer.affinity().unwrap().thread;
+			lingerer = spawn(move || {
+				resume(&mut lingerer, 100).unwrap();
+				if let Some(affinity) = lingerer.affinity() {
+					assert!(affinity.thread == current().id());
+				}
+				lingerer
+			}).join().unwrap();
+			if let Some(affinity) = lingerer.affinity() {
+				assert!(affinity.thread != before);
+			}
+		}
+		assert!(lingerer.is_completion());
 	});
 }
 
@@ -150,10 +821,42 @@ fn launch_toomany_reinit() {
 		let thing_one = launch(|| timeout(1_000_000), 0).unwrap();
 		let _thing_two = launch(|| timeout(1_000_000), 0).unwrap();
 		drop(thing_one);
+		renewed();
 		let _thing_three = launch(|| timeout(1_000_000), 0).unwrap();
 	});
 }
 
+#[test]
+fn launch_threads() {
+	exclusive(|| {
+		use std::sync::Arc;
+		use std::sync::Barrier;
+		use std::sync::Mutex;
+		use std::thread::spawn;
+
+		// More than the number of distinct signals that could possibly be available.
+		const THREADS: usize = 64;
+
+		// Keep every thread (and therefore its timer) alive until all of them have launched,
+		// but don't let them compete for libsets.
+		let barrier = Arc::new(Barrier::new(THREADS));
+		let serial = Arc::new(Mutex::new(()));
+		let threads: Vec<_> = (0..THREADS).map(|_| {
+			let barrier = barrier.clone();
+			let serial = serial.clone();
+			spawn(move || {
+				let serial = serial.lock().unwrap();
+				assert!(launch(|| (), 1_000).unwrap().is_completion());
+				drop(serial);
+				barrier.wait();
+			})
+		}).collect();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+	});
+}
+
 #[ignore]
 #[test]
 fn abuse_preemption() {
@@ -162,6 +865,18 @@ fn abuse_preemption() {
 	}
 }
 
+fn recurse(depth: usize) -> u8 {
+	let mut frame = [0; 4_096];
+	frame[depth % frame.len()] = depth as _;
+	if depth == 0 {
+		0
+	} else {
+		recurse(depth - 1).wrapping_add(unsafe {
+			std::ptr::read_volatile(&frame[depth % frame.len()])
+		})
+	}
+}
+
 fn timeout(mut useconds: u64) {
 	useconds *= 1_000;
 
@@ -178,6 +893,29 @@ fn timeout(mut useconds: u64) {
 	}
 }
 
+fn stack(stack: usize) -> LaunchOptions {
+	LaunchOptions {
+		stack,
+		..LaunchOptions::default()
+	}
+}
+
+fn high() -> LaunchOptions {
+	LaunchOptions {
+		priority: Priority::High,
+		..stack(64 * 1_024)
+	}
+}
+
+#[inline(never)]
+fn spin() {
+	use std::hint::black_box;
+
+	for _ in 0..1_000 {
+		assert!(black_box(true));
+	}
+}
+
 #[bench]
 #[cfg(bench)]
 fn timeout_10(lo: &mut Bencher) {